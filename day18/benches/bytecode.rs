@@ -0,0 +1,90 @@
+use std::env;
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use day18::ast::{Expr, Opcode};
+use day18::bytecode::Code;
+use day18::eval::{Env, Modular};
+use day18::evaluate;
+
+// A random tree of + and * over `leaves` digits. Splitting at a random point
+// keeps it about as deep as a random binary search tree, which the tree
+// walker can still handle.
+fn generate(leaves: usize, rng: &mut StdRng) -> Box<Expr> {
+    if leaves == 1 {
+        return Box::new(Expr::Number(rng.random_range(1..10).to_string()));
+    }
+    let left = rng.random_range(1..leaves);
+    let op = if rng.random_bool(0.5) {
+        Opcode::Add
+    } else {
+        Opcode::Mul
+    };
+    Box::new(Expr::Op(
+        generate(left, rng),
+        op,
+        generate(leaves - left, rng),
+    ))
+}
+
+// `1 + 1 + ... + 1` as the left-to-right table parses it: one level of
+// nesting per operator, far too deep for the tree walker.
+fn chain(leaves: usize) -> Box<Expr> {
+    let mut expr = Box::new(Expr::Number("1".to_owned()));
+    for _ in 1..leaves {
+        expr = Box::new(Expr::Op(
+            expr,
+            Opcode::Add,
+            Box::new(Expr::Number("1".to_owned())),
+        ));
+    }
+    expr
+}
+
+fn main() {
+    let sizes: Vec<usize> = env::args()
+        .skip(1)
+        .filter_map(|arg| arg.parse().ok())
+        .collect();
+    let sizes = if sizes.is_empty() {
+        vec![1_000, 100_000, 1_000_000]
+    } else {
+        sizes
+    };
+    let backend = Modular::new(1_000_000_007).unwrap();
+    let env = Env::new();
+    for leaves in sizes {
+        let mut rng = StdRng::seed_from_u64(0x2020_0018 ^ leaves as u64);
+        let expr = generate(leaves, &mut rng);
+
+        let start = Instant::now();
+        let walked = evaluate(&expr, &backend).unwrap();
+        let walk_time = start.elapsed();
+
+        let start = Instant::now();
+        let code = Code::compile(&expr);
+        let compile_time = start.elapsed();
+        let start = Instant::now();
+        let run = code.run(&backend, &env).unwrap();
+        let run_time = start.elapsed();
+
+        assert_eq!(walked, run);
+        println!(
+            "{:>8} leaves: tree walker {:?}, compile {:?}, stack machine {:?}",
+            leaves, walk_time, compile_time, run_time
+        );
+
+        let expr = chain(leaves);
+        let start = Instant::now();
+        let value = Code::compile(&expr).run(&backend, &env).unwrap();
+        assert_eq!(value.value(), leaves as u128);
+        println!(
+            "{:>8} leaves: chain of depth {} on the stack machine {:?}",
+            leaves,
+            leaves - 1,
+            start.elapsed()
+        );
+    }
+}
@@ -1,24 +1,126 @@
 use std::fmt::{Debug, Error, Formatter};
+use std::mem;
 
 pub enum Expr {
-    Number(i32),
-    Op(Box<Expr>, Opcode, Box<Expr>)
+    // the literal's digits as written, read by each backend into its own type
+    Number(String),
+    Var(String),
+    Call(String, Vec<Box<Expr>>),
+    Neg(Box<Expr>),
+    Op(Box<Expr>, Opcode, Box<Expr>),
+}
+
+impl Expr {
+    // Moves the expression out of `slot`, leaving an empty literal in its
+    // place. Expr can't be taken apart by moving out of it since it implements
+    // Drop.
+    pub fn take(slot: &mut Box<Expr>) -> Box<Expr> {
+        mem::replace(slot, Box::new(Expr::Number(String::new())))
+    }
+}
+
+// Dropping a tree one level at a time would recurse as deep as the tree, so
+// the subtrees are taken out into a list and dropped from there.
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        take_subtrees(self, &mut pending);
+        while let Some(mut expr) = pending.pop() {
+            take_subtrees(&mut expr, &mut pending);
+        }
+    }
+}
+
+// Moves the children of `expr` that have children of their own to `out`
+fn take_subtrees(expr: &mut Expr, out: &mut Vec<Expr>) {
+    let mut take = |child: &mut Box<Expr>| {
+        if matches!(**child, Expr::Neg(_) | Expr::Op(..) | Expr::Call(..)) {
+            out.push(*Expr::take(child));
+        }
+    };
+    match expr {
+        Expr::Number(_) | Expr::Var(_) => {}
+        Expr::Call(_, args) => args.iter_mut().for_each(take),
+        Expr::Neg(e) => take(e),
+        Expr::Op(l, _, r) => {
+            take(l);
+            take(r);
+        }
+    }
 }
 
 impl Debug for Expr {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         use self::Expr::*;
         match *self {
-            Number(n) => write!(fmt, "{:?}", n),
+            Number(ref n) => write!(fmt, "{}", n),
+            Var(ref name) => write!(fmt, "{}", name),
+            Call(ref name, ref args) => {
+                write!(fmt, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{:?}", arg)?;
+                }
+                write!(fmt, ")")
+            }
+            Neg(ref e) => write!(fmt, "-{:?}", e),
             Op(ref l, op, ref r) => write!(fmt, "({:?} {:?} {:?})", l, op, r),
         }
     }
 }
 
-#[derive(Copy, Clone)]
+// One line of homework: a bare expression, `x = <expr>` or `f(a, b) = <expr>`.
+pub enum Stmt {
+    Expr(Box<Expr>),
+    Assign(String, Box<Expr>),
+    Def(String, Vec<String>, Box<Expr>),
+}
+
+impl Stmt {
+    // Builds the statement for `lhs = rhs`, None unless lhs is a variable or a
+    // call whose arguments are all distinct variables.
+    pub fn assignment(mut lhs: Expr, rhs: Box<Expr>) -> Option<Stmt> {
+        match &mut lhs {
+            Expr::Var(name) => Some(Stmt::Assign(mem::take(name), rhs)),
+            Expr::Call(name, args) => {
+                let mut params: Vec<String> = Vec::new();
+                for arg in args {
+                    match &mut **arg {
+                        Expr::Var(param) if !params.contains(param) => {
+                            params.push(mem::take(param))
+                        }
+                        _ => return None,
+                    }
+                }
+                Some(Stmt::Def(mem::take(name), params, rhs))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Debug for Stmt {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            Stmt::Expr(ref e) => write!(fmt, "{:?}", e),
+            Stmt::Assign(ref name, ref e) => write!(fmt, "{} = {:?}", name, e),
+            Stmt::Def(ref name, ref params, ref e) => {
+                write!(fmt, "{}({}) = {:?}", name, params.join(", "), e)
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Opcode {
     Add,
-    Mul
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
 }
 
 impl Debug for Opcode {
@@ -26,7 +128,102 @@ impl Debug for Opcode {
         use self::Opcode::*;
         match *self {
             Mul => write!(fmt, "*"),
+            Div => write!(fmt, "/"),
+            Mod => write!(fmt, "%"),
             Add => write!(fmt, "+"),
+            Sub => write!(fmt, "-"),
+            Pow => write!(fmt, "^"),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(n: &str) -> Expr {
+        Expr::Number(n.to_owned())
+    }
+
+    fn var(name: &str) -> Expr {
+        Expr::Var(name.to_owned())
+    }
+
+    fn neg(e: Expr) -> Expr {
+        Expr::Neg(Box::new(e))
+    }
+
+    fn op(l: Expr, op: Opcode, r: Expr) -> Expr {
+        Expr::Op(Box::new(l), op, Box::new(r))
+    }
+
+    fn call(name: &str, args: Vec<Expr>) -> Expr {
+        Expr::Call(name.to_owned(), args.into_iter().map(Box::new).collect())
+    }
+
+    #[test]
+    fn debug_output() {
+        let exp = op(
+            neg(op(num("1"), Opcode::Add, var("x"))),
+            Opcode::Pow,
+            call("f", vec![num("2"), var("y"), call("g", vec![])]),
+        );
+        assert_eq!(format!("{:?}", exp), "(-(1 + x) ^ f(2, y, g()))");
+        let ops = [
+            Opcode::Add,
+            Opcode::Sub,
+            Opcode::Mul,
+            Opcode::Div,
+            Opcode::Mod,
+            Opcode::Pow,
+        ];
+        let ops: Vec<String> = ops.iter().map(|op| format!("{:?}", op)).collect();
+        assert_eq!(ops, ["+", "-", "*", "/", "%", "^"]);
+    }
+
+    #[test]
+    fn assignments() {
+        let stmt = Stmt::assignment(var("x"), Box::new(num("3"))).unwrap();
+        assert_eq!(format!("{:?}", stmt), "x = 3");
+        let lhs = call("f", vec![var("a"), var("b")]);
+        let rhs = op(var("a"), Opcode::Mul, var("b"));
+        let stmt = Stmt::assignment(lhs, Box::new(rhs)).unwrap();
+        assert_eq!(format!("{:?}", stmt), "f(a, b) = (a * b)");
+        let stmt = Stmt::assignment(call("k", vec![]), Box::new(num("1"))).unwrap();
+        assert_eq!(format!("{:?}", stmt), "k() = 1");
+        assert_eq!(format!("{:?}", Stmt::Expr(Box::new(var("x")))), "x");
+    }
+
+    #[test]
+    fn bad_assignments() {
+        let bad = |lhs| Stmt::assignment(lhs, Box::new(num("2"))).is_none();
+        assert!(bad(num("1")));
+        assert!(bad(neg(var("x"))));
+        assert!(bad(op(num("1"), Opcode::Add, var("x"))));
+        // parameters have to be plain and distinct
+        assert!(bad(call("f", vec![var("a"), num("1")])));
+        assert!(bad(call("f", vec![var("a"), var("a")])));
+    }
+
+    #[test]
+    fn take_leaves_an_empty_literal() {
+        let mut slot = Box::new(neg(var("x")));
+        let taken = Expr::take(&mut slot);
+        assert_eq!(format!("{:?}", taken), "-x");
+        assert!(matches!(*slot, Expr::Number(ref n) if n.is_empty()));
+    }
+
+    // dropping goes through a list rather than down the tree
+    #[test]
+    fn deep_trees_drop() {
+        let mut exp = num("1");
+        for i in 0..1_000_000 {
+            exp = match i % 3 {
+                0 => neg(exp),
+                1 => op(exp, Opcode::Add, num("1")),
+                _ => call("f", vec![num("2"), exp]),
+            };
+        }
+        drop(exp);
+    }
+}
@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::{Expr, Opcode, Stmt};
+use crate::eval::{callee, Backend, Env, EvalError, Failure, FAILURE_CONTEXT};
+
+// One stack machine instruction. Operands are popped right first, so `Apply`
+// sees the left operand below the right one, in source order. `Check` comes
+// before a call's arguments and makes sure the function can be called at all,
+// so errors come in the same order as from the tree walker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    Push(String),
+    Load(String),
+    Neg,
+    Apply(Opcode),
+    Check(String, usize),
+    Call(String, usize),
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Push(n) => write!(f, "push {}", n),
+            Op::Load(name) => write!(f, "load {}", name),
+            Op::Neg => write!(f, "neg"),
+            Op::Apply(Opcode::Add) => write!(f, "add"),
+            Op::Apply(Opcode::Sub) => write!(f, "sub"),
+            Op::Apply(Opcode::Mul) => write!(f, "mul"),
+            Op::Apply(Opcode::Div) => write!(f, "div"),
+            Op::Apply(Opcode::Mod) => write!(f, "mod"),
+            Op::Apply(Opcode::Pow) => write!(f, "pow"),
+            Op::Check(name, argc) => write!(f, "check {} {}", name, argc),
+            Op::Call(name, argc) => write!(f, "call {} {}", name, argc),
+        }
+    }
+}
+
+// An expression in postfix order. `starts[i]` is where the sub-expression
+// ending at `ops[i]` begins, so errors can name it like the tree walker does;
+// a call's begins at its `Check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Code {
+    ops: Vec<Op>,
+    starts: Vec<usize>,
+}
+
+enum Visit<'a> {
+    Enter(&'a Expr),
+    Exit(&'a Expr, usize),
+}
+
+impl Code {
+    // Flattens `expr` without recursing, however deeply it is nested.
+    pub fn compile(expr: &Expr) -> Code {
+        let mut code = Code {
+            ops: Vec::new(),
+            starts: Vec::new(),
+        };
+        let mut work = vec![Visit::Enter(expr)];
+        while let Some(visit) = work.pop() {
+            match visit {
+                Visit::Enter(expr) => {
+                    work.push(Visit::Exit(expr, code.ops.len()));
+                    match expr {
+                        Expr::Number(_) | Expr::Var(_) => {}
+                        Expr::Neg(e) => work.push(Visit::Enter(e)),
+                        Expr::Op(l, _, r) => {
+                            work.push(Visit::Enter(r));
+                            work.push(Visit::Enter(l));
+                        }
+                        Expr::Call(name, args) => {
+                            code.ops.push(Op::Check(name.clone(), args.len()));
+                            code.starts.push(code.ops.len() - 1);
+                            work.extend(args.iter().rev().map(|arg| Visit::Enter(arg)))
+                        }
+                    }
+                }
+                Visit::Exit(expr, start) => {
+                    code.ops.push(match expr {
+                        Expr::Number(n) => Op::Push(n.clone()),
+                        Expr::Var(name) => Op::Load(name.clone()),
+                        Expr::Neg(_) => Op::Neg,
+                        Expr::Op(_, op, _) => Op::Apply(*op),
+                        Expr::Call(name, args) => Op::Call(name.clone(), args.len()),
+                    });
+                    code.starts.push(start);
+                }
+            }
+        }
+        code
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    // The sub-expression ending at `ops[end]`, printed as Expr's Debug would
+    // but only as far as a Failure keeps of it. Operands are found through
+    // `starts` and written straight out, so a long expression costs no more
+    // than a short one.
+    fn source(&self, end: usize) -> String {
+        enum Piece {
+            Expr(usize),
+            Text(String),
+        }
+        let mut text = String::new();
+        let mut work = vec![Piece::Expr(end)];
+        while let Some(piece) = work.pop() {
+            if text.len() > FAILURE_CONTEXT {
+                break;
+            }
+            let end = match piece {
+                Piece::Text(piece) => {
+                    text.push_str(&piece);
+                    continue;
+                }
+                Piece::Expr(end) => end,
+            };
+            match &self.ops[end] {
+                Op::Push(n) => text.push_str(n),
+                Op::Load(name) => text.push_str(name),
+                Op::Neg => {
+                    text.push('-');
+                    work.push(Piece::Expr(end - 1));
+                }
+                Op::Apply(op) => {
+                    let r = end - 1;
+                    text.push('(');
+                    work.push(Piece::Text(")".to_owned()));
+                    work.push(Piece::Expr(r));
+                    work.push(Piece::Text(format!(" {:?} ", op)));
+                    work.push(Piece::Expr(self.starts[r] - 1));
+                }
+                Op::Call(name, argc) => {
+                    text.push_str(name);
+                    text.push('(');
+                    work.push(Piece::Text(")".to_owned()));
+                    // the last argument ends just before the call, each one
+                    // before it just before the next one starts
+                    let mut next = end;
+                    for i in (0..*argc).rev() {
+                        work.push(Piece::Expr(next - 1));
+                        next = self.starts[next - 1];
+                        if i > 0 {
+                            work.push(Piece::Text(", ".to_owned()));
+                        }
+                    }
+                }
+                Op::Check(..) => unreachable!("checks are never operands"),
+            }
+        }
+        text
+    }
+
+    // the `Call` a `Check` stands for: the first op after it to start there
+    fn call_of(&self, check: usize) -> usize {
+        (check + 1..self.ops.len())
+            .find(|&i| self.starts[i] == check)
+            .unwrap()
+    }
+
+    // Runs the code against the globals in `env`. Function bodies are compiled
+    // the first time they are called and run in a frame of their own, so nested
+    // calls don't grow the native stack either.
+    pub fn run<B: Backend>(&self, backend: &B, env: &Env<B::Value>) -> Result<B::Value, Failure> {
+        let mut compiled: HashMap<String, Rc<Code>> = HashMap::new();
+        let mut frames: Vec<Frame<B::Value>> = Vec::new();
+        // None while running `self`
+        let mut body: Option<Rc<Code>> = None;
+        let mut pc = 0;
+        let mut locals = HashMap::new();
+        let mut stack: Vec<B::Value> = Vec::new();
+        loop {
+            let code = body.as_deref().unwrap_or(self);
+            if pc == code.ops.len() {
+                match frames.pop() {
+                    // the body's value is left on top of the stack
+                    Some(frame) => {
+                        body = frame.body;
+                        pc = frame.pc;
+                        locals = frame.locals;
+                        continue;
+                    }
+                    None => return Ok(stack.pop().unwrap()),
+                }
+            }
+            let fail = |error| Failure::with_text(error, code.source(pc));
+            let value = match &code.ops[pc] {
+                Op::Push(n) => backend.number(n),
+                Op::Load(name) => match locals.get(name.as_str()).or_else(|| env.var(name)) {
+                    Some(value) => Ok(value.clone()),
+                    None => Err(EvalError::UndefinedVariable(name.clone())),
+                },
+                Op::Neg => {
+                    let v = stack.pop().unwrap();
+                    backend.neg(v)
+                }
+                Op::Apply(op) => {
+                    let r = stack.pop().unwrap();
+                    let l = stack.pop().unwrap();
+                    backend.apply(*op, l, r)
+                }
+                Op::Check(name, argc) => {
+                    if let Err(error) = callee(env, name, *argc, frames.len()) {
+                        let call = code.source(code.call_of(pc));
+                        return Err(Failure::with_text(error, call));
+                    }
+                    pc += 1;
+                    continue;
+                }
+                Op::Call(name, argc) => {
+                    // the check before the arguments found it
+                    let function = env.function(name).unwrap();
+                    let callee = compiled
+                        .entry(name.clone())
+                        .or_insert_with(|| Rc::new(Code::compile(&function.body)))
+                        .clone();
+                    let args = stack.split_off(stack.len() - argc);
+                    let params = function.params.iter().map(|p| p.as_str());
+                    frames.push(Frame {
+                        body: body.replace(callee),
+                        pc: pc + 1,
+                        locals: std::mem::replace(&mut locals, params.zip(args).collect()),
+                    });
+                    pc = 0;
+                    continue;
+                }
+            };
+            stack.push(value.map_err(fail)?);
+            pc += 1;
+        }
+    }
+}
+
+// Where to pick up again once a function body is done
+struct Frame<'e, V> {
+    body: Option<Rc<Code>>,
+    pc: usize,
+    locals: HashMap<&'e str, V>,
+}
+
+pub fn evaluate<B: Backend>(
+    expr: &Expr,
+    backend: &B,
+    env: &Env<B::Value>,
+) -> Result<B::Value, Failure> {
+    Code::compile(expr).run(backend, env)
+}
+
+// eval::execute on the stack machine
+pub fn execute<B: Backend>(
+    stmt: Stmt,
+    backend: &B,
+    env: &mut Env<B::Value>,
+) -> Result<Option<B::Value>, Failure> {
+    match stmt {
+        Stmt::Expr(e) => evaluate(&e, backend, env).map(Some),
+        Stmt::Assign(name, e) => {
+            let value = evaluate(&e, backend, env)?;
+            env.set_var(&name, value);
+            Ok(None)
+        }
+        Stmt::Def(name, params, body) => {
+            env.define(&name, params, body);
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::eval::{evaluate_in, Int128};
+    use crate::pratt::{self, PrecedenceTable};
+    use crate::random;
+
+    // runs both ways and checks they agree, values and failures alike
+    fn agree(text: &str, table: &PrecedenceTable, env: &Env<i128>) -> Result<i128, Failure> {
+        let exp = pratt::parse(text, table).unwrap();
+        let value = evaluate(&exp, &Int128, env);
+        assert_eq!(value, evaluate_in(&exp, &Int128, env), "{}", text);
+        value
+    }
+
+    fn define(env: &mut Env<i128>, lines: &[&str]) {
+        let table = PrecedenceTable::addition_first();
+        for line in lines {
+            let stmt = pratt::parse_stmt(line, &table).unwrap();
+            assert_eq!(execute(stmt, &Int128, env), Ok(None));
+        }
+    }
+
+    #[test]
+    fn puzzle_examples() {
+        let left_to_right = PrecedenceTable::left_to_right();
+        let addition_first = PrecedenceTable::addition_first();
+        let none = Env::new();
+        for (text, part1, part2) in &[
+            ("1 + 2 * 3 + 4 * 5 + 6", 71, 231),
+            ("1 + (2 * 3) + (4 * (5 + 6))", 51, 51),
+            ("5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))", 12240, 669060),
+            ("10 - 2 * 3 + 1", 25, 32),
+            ("7 - 10 / 2 % 3", -1, -1),
+            ("2 ^ 3 ^ 2 + 1", 65, 513),
+            ("-3 * -(2 + 1)", 9, 9),
+        ] {
+            assert_eq!(agree(text, &left_to_right, &none), Ok(*part1));
+            assert_eq!(agree(text, &addition_first, &none), Ok(*part2));
+        }
+        let failure = agree("7 + 1 / (3 - 3)", &left_to_right, &none).unwrap_err();
+        assert_eq!(failure.error, EvalError::DivisionByZero);
+        assert_eq!(failure.expr, "((7 + 1) / (3 - 3))");
+    }
+
+    #[test]
+    fn variables_and_functions() {
+        let table = PrecedenceTable::addition_first();
+        let mut env = Env::new();
+        define(
+            &mut env,
+            &["x = 3 + 4", "f(a, b) = a * (b + 1)", "y = f(x, 2) + x"],
+        );
+        assert_eq!(agree("f(y, x) - y", &table, &env), Ok(196));
+        define(&mut env, &["g(n) = 2 * n", "h(n) = n + h(n - 1)"]);
+        let failure = agree("g(x) + z", &table, &env).unwrap_err();
+        assert_eq!(failure.error, EvalError::UndefinedVariable("z".to_owned()));
+        let failure = agree("1 + f(x, g(y / 0))", &table, &env).unwrap_err();
+        assert_eq!(failure.error, EvalError::DivisionByZero);
+        assert_eq!(failure.expr, "(y / 0)");
+        let failure = agree("h(1)", &table, &env).unwrap_err();
+        assert_eq!(failure.error, EvalError::RecursionLimit);
+    }
+
+    // a call that can't be made fails before its arguments are worked out,
+    // as it does in the tree walker
+    #[test]
+    fn calls_are_checked_first() {
+        let table = PrecedenceTable::addition_first();
+        let mut env = Env::new();
+        define(&mut env, &["g(n) = 2 * n"]);
+        let failure = agree("1 + k(1 / 0, 2)", &table, &env).unwrap_err();
+        assert_eq!(failure.error, EvalError::UndefinedFunction("k".to_owned()));
+        assert_eq!(failure.expr, "k((1 / 0), 2)");
+        let failure = agree("g(z, 1 / 0)", &table, &env).unwrap_err();
+        assert_eq!(
+            failure.error,
+            EvalError::WrongArity {
+                name: "g".to_owned(),
+                expected: 1,
+                found: 2
+            }
+        );
+        assert_eq!(failure.expr, "g(z, (1 / 0))");
+        define(&mut env, &["d(n) = d(n / 0)"]);
+        let failure = agree("d(1)", &table, &env).unwrap_err();
+        assert_eq!(failure.error, EvalError::DivisionByZero);
+        let code = Code::compile(&pratt::parse("g(1) + 2", &table).unwrap());
+        let ops: Vec<String> = code.ops().iter().map(|op| op.to_string()).collect();
+        assert_eq!(ops, ["check g 1", "push 1", "call g 1", "push 2", "add"]);
+        // the check in the deepest frame is the one that fails
+        define(&mut env, &["r(n) = r(n - 1)"]);
+        let failure = agree("r(1)", &table, &env).unwrap_err();
+        assert_eq!(failure.error, EvalError::RecursionLimit);
+        assert_eq!(failure.expr, "r((n - 1))");
+    }
+
+    #[test]
+    fn matches_the_tree_walker() {
+        let mut rng = StdRng::seed_from_u64(0x2020_0018);
+        let none = Env::new();
+        for _ in 0..2000 {
+            let exp = random::expr(&mut rng, 6);
+            assert_eq!(
+                evaluate(&exp, &Int128, &none),
+                evaluate_in(&exp, &Int128, &none),
+                "{:?}",
+                exp
+            );
+        }
+    }
+
+    // far deeper than the tree walker could go, and failing at the end costs
+    // no more than failing at the start
+    #[test]
+    fn deep_chains() {
+        let table = PrecedenceTable::left_to_right();
+        let none = Env::new();
+        let chain = vec!["1"; 100_000].join(" + ");
+        let exp = pratt::parse(&chain, &table).unwrap();
+        assert_eq!(evaluate(&exp, &Int128, &none), Ok(100_000));
+        let exp = pratt::parse(&format!("{} / 0", chain), &table).unwrap();
+        let failure = evaluate(&exp, &Int128, &none).unwrap_err();
+        assert_eq!(failure.error, EvalError::DivisionByZero);
+        assert_eq!(failure.expr.len(), FAILURE_CONTEXT + 3);
+        assert!(failure.expr.starts_with("((((("));
+        assert!(failure.expr.ends_with("..."));
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+
+use crate::pratt;
+
+// A parse error located on one input line. `start..end` is a byte span of the
+// line; an empty span points just past the end. `column` is where the span
+// starts counted in characters from 1, as an editor would show it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+    pub expected: Vec<String>,
+}
+
+// characters in `source` before byte `offset`, all of them past the end
+fn chars_before(source: &str, offset: usize) -> usize {
+    source
+        .get(..offset)
+        .map_or(source.chars().count(), |before| before.chars().count())
+}
+
+impl Diagnostic {
+    // `source` is the line that was parsed
+    pub fn from_pratt(line: usize, source: &str, error: pratt::ParseError) -> Diagnostic {
+        Diagnostic {
+            line,
+            column: chars_before(source, error.start) + 1,
+            start: error.start,
+            end: error.end,
+            message: error.message,
+            expected: error.expected,
+        }
+    }
+
+    // The message followed by the offending source line with carets under the span.
+    pub fn render(&self, source: &str) -> String {
+        let column = chars_before(source, self.start);
+        let width = source
+            .get(self.start..self.end)
+            .map_or(1, |span| span.chars().count().max(1));
+        format!(
+            "{}\n    {}\n    {}{}",
+            self,
+            source,
+            " ".repeat(column),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )?;
+        match self.expected.as_slice() {
+            [] => Ok(()),
+            [only] => write!(f, ", expected {}", only),
+            [init @ .., last] => write!(f, ", expected one of {} or {}", init.join(", "), last),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pratt::{self, PrecedenceTable};
+
+    fn diagnostic(source: &str) -> Diagnostic {
+        let error = pratt::parse(source, &PrecedenceTable::standard()).unwrap_err();
+        Diagnostic::from_pratt(1, source, error)
+    }
+
+    #[test]
+    fn columns_count_characters() {
+        let source = "f(é) + é";
+        let diagnostic = diagnostic(source);
+        assert_eq!((diagnostic.start, diagnostic.column), (2, 3));
+        assert_eq!(
+            diagnostic.render(source),
+            "line 1, column 3: invalid character\n    f(é) + é\n      ^"
+        );
+    }
+
+    #[test]
+    fn end_of_line_points_past_the_last_character() {
+        // a no-break space is whitespace to the tokenizer but two bytes long
+        let source = "1 +\u{a0}(";
+        let diagnostic = diagnostic(source);
+        assert_eq!(diagnostic.column, 6);
+        assert!(diagnostic.render(source).ends_with("\n         ^"));
+    }
+}
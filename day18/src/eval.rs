@@ -0,0 +1,687 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+
+use num_bigint::{BigInt, Sign};
+
+use crate::ast::{Expr, Opcode, Stmt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    DivisionByZero,
+    NegativeExponent,
+    ExponentTooLarge,
+    // modular arithmetic lost track of the exponent as a plain integer
+    InexactExponent,
+    NotInvertible,
+    Overflow,
+    UndefinedVariable(String),
+    UndefinedFunction(String),
+    WrongArity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    RecursionLimit,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::NegativeExponent => write!(f, "negative exponent"),
+            EvalError::ExponentTooLarge => write!(f, "exponent too large"),
+            EvalError::InexactExponent => {
+                write!(f, "exponent is only known modulo the modulus")
+            }
+            EvalError::NotInvertible => write!(f, "divisor has no inverse for the modulus"),
+            EvalError::Overflow => write!(f, "arithmetic overflow"),
+            EvalError::UndefinedVariable(name) => write!(f, "undefined variable {}", name),
+            EvalError::UndefinedFunction(name) => write!(f, "undefined function {}", name),
+            EvalError::WrongArity {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} takes {} argument(s) but was given {}",
+                name, expected, found
+            ),
+            EvalError::RecursionLimit => write!(f, "too many nested calls"),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+// An error together with the sub-expression that raised it, cut short when the
+// expression is too long to be worth printing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub error: EvalError,
+    pub expr: String,
+}
+
+pub(crate) const FAILURE_CONTEXT: usize = 120;
+
+impl Failure {
+    pub(crate) fn new(error: EvalError, expr: &Expr) -> Failure {
+        Failure::with_text(error, format!("{:?}", expr))
+    }
+
+    // `text` is the offending sub-expression as printed by Expr's Debug
+    pub(crate) fn with_text(error: EvalError, mut text: String) -> Failure {
+        if text.len() > FAILURE_CONTEXT {
+            let mut end = FAILURE_CONTEXT;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            text.truncate(end);
+            text.push_str("...");
+        }
+        Failure { error, expr: text }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} in {}", self.error, self.expr)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+// The arithmetic used by `evaluate`. Every operation reports failure instead
+// of wrapping or panicking, so a result is never silently wrong.
+// Literals come in as their digits, so each backend takes numbers as wide as
+// it can hold.
+pub trait Backend {
+    type Value: Clone + fmt::Display;
+
+    fn number(&self, digits: &str) -> Result<Self::Value, EvalError>;
+    fn neg(&self, v: Self::Value) -> Result<Self::Value, EvalError>;
+    fn apply(&self, op: Opcode, l: Self::Value, r: Self::Value) -> Result<Self::Value, EvalError>;
+}
+
+// Nested calls allowed before giving up; with no conditionals a recursive
+// function can never stop, so this only ever catches runaway recursion.
+pub const MAX_DEPTH: usize = 256;
+
+pub struct Function {
+    pub params: Vec<String>,
+    pub body: Box<Expr>,
+}
+
+// Variables and functions defined by earlier lines. Function bodies see their
+// parameters and the globals as they are when the function is called.
+pub struct Env<V> {
+    vars: HashMap<String, V>,
+    functions: HashMap<String, Rc<Function>>,
+}
+
+impl<V: Clone> Env<V> {
+    pub fn new() -> Env<V> {
+        Env {
+            vars: HashMap::new(),
+            functions: HashMap::new(),
+        }
+    }
+
+    pub fn var(&self, name: &str) -> Option<&V> {
+        self.vars.get(name)
+    }
+
+    pub fn set_var(&mut self, name: &str, value: V) {
+        self.vars.insert(name.to_owned(), value);
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name).map(|f| &**f)
+    }
+
+    pub fn define(&mut self, name: &str, params: Vec<String>, body: Box<Expr>) {
+        self.functions
+            .insert(name.to_owned(), Rc::new(Function { params, body }));
+    }
+}
+
+impl<V: Clone> Default for Env<V> {
+    fn default() -> Env<V> {
+        Env::new()
+    }
+}
+
+struct Frame<'a, V> {
+    locals: HashMap<&'a str, V>,
+    depth: usize,
+}
+
+fn eval<B: Backend>(
+    expr: &Expr,
+    backend: &B,
+    env: &Env<B::Value>,
+    frame: &Frame<B::Value>,
+) -> Result<B::Value, Failure> {
+    let value = match *expr {
+        Expr::Number(ref digits) => backend.number(digits),
+        Expr::Var(ref name) => match frame.locals.get(name.as_str()).or_else(|| env.var(name)) {
+            Some(value) => Ok(value.clone()),
+            None => Err(EvalError::UndefinedVariable(name.clone())),
+        },
+        Expr::Call(ref name, ref args) => {
+            let function = callee(env, name, args.len(), frame.depth)
+                .map_err(|error| Failure::new(error, expr))?;
+            let mut locals = HashMap::new();
+            for (param, arg) in function.params.iter().zip(args.iter()) {
+                locals.insert(param.as_str(), eval(arg, backend, env, frame)?);
+            }
+            let inner = Frame {
+                locals,
+                depth: frame.depth + 1,
+            };
+            return eval(&function.body, backend, env, &inner);
+        }
+        Expr::Neg(ref e) => backend.neg(eval(e, backend, env, frame)?),
+        Expr::Op(ref l, op, ref r) => {
+            let l = eval(l, backend, env, frame)?;
+            let r = eval(r, backend, env, frame)?;
+            backend.apply(op, l, r)
+        }
+    };
+    value.map_err(|error| Failure::new(error, expr))
+}
+
+// The function a call at `depth` runs, once it is known to exist, to take
+// `argc` arguments and to have room for another frame
+pub(crate) fn callee<V: Clone>(
+    env: &Env<V>,
+    name: &str,
+    argc: usize,
+    depth: usize,
+) -> Result<Rc<Function>, EvalError> {
+    let function = match env.functions.get(name) {
+        Some(function) => Rc::clone(function),
+        None => return Err(EvalError::UndefinedFunction(name.to_owned())),
+    };
+    if function.params.len() != argc {
+        return Err(EvalError::WrongArity {
+            name: name.to_owned(),
+            expected: function.params.len(),
+            found: argc,
+        });
+    }
+    if depth >= MAX_DEPTH {
+        return Err(EvalError::RecursionLimit);
+    }
+    Ok(function)
+}
+
+// The value of `expr`, a call to `name`, with its arguments already worked out
+pub(crate) fn call<B: Backend>(
+    expr: &Expr,
+    name: &str,
+    args: Vec<B::Value>,
+    backend: &B,
+    env: &Env<B::Value>,
+) -> Result<B::Value, Failure> {
+    let function = callee(env, name, args.len(), 0).map_err(|error| Failure::new(error, expr))?;
+    let locals = function
+        .params
+        .iter()
+        .map(|p| p.as_str())
+        .zip(args)
+        .collect();
+    eval(&function.body, backend, env, &Frame { locals, depth: 1 })
+}
+
+pub fn evaluate_in<B: Backend>(
+    expr: &Expr,
+    backend: &B,
+    env: &Env<B::Value>,
+) -> Result<B::Value, Failure> {
+    let frame = Frame {
+        locals: HashMap::new(),
+        depth: 0,
+    };
+    eval(expr, backend, env, &frame)
+}
+
+pub fn evaluate<B: Backend>(expr: &Expr, backend: &B) -> Result<B::Value, Failure> {
+    evaluate_in(expr, backend, &Env::new())
+}
+
+// Runs one line against `env`. Expressions give back their value, assignments
+// and definitions update `env` and give back None.
+pub fn execute<B: Backend>(
+    stmt: Stmt,
+    backend: &B,
+    env: &mut Env<B::Value>,
+) -> Result<Option<B::Value>, Failure> {
+    match stmt {
+        Stmt::Expr(e) => evaluate_in(&e, backend, env).map(Some),
+        Stmt::Assign(name, e) => {
+            let value = evaluate_in(&e, backend, env)?;
+            env.set_var(&name, value);
+            Ok(None)
+        }
+        Stmt::Def(name, params, body) => {
+            env.define(&name, params, body);
+            Ok(None)
+        }
+    }
+}
+
+// Signed 128 bit integers, `/` and `%` truncate toward zero.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Int128;
+
+impl Backend for Int128 {
+    type Value = i128;
+
+    fn number(&self, digits: &str) -> Result<i128, EvalError> {
+        digits.parse().map_err(|_| EvalError::Overflow)
+    }
+
+    fn neg(&self, v: i128) -> Result<i128, EvalError> {
+        v.checked_neg().ok_or(EvalError::Overflow)
+    }
+
+    fn apply(&self, op: Opcode, l: i128, r: i128) -> Result<i128, EvalError> {
+        let value = match op {
+            Opcode::Add => l.checked_add(r),
+            Opcode::Sub => l.checked_sub(r),
+            Opcode::Mul => l.checked_mul(r),
+            Opcode::Div | Opcode::Mod if r == 0 => return Err(EvalError::DivisionByZero),
+            Opcode::Div => l.checked_div(r),
+            Opcode::Mod => l.checked_rem(r),
+            Opcode::Pow if r < 0 => return Err(EvalError::NegativeExponent),
+            Opcode::Pow => u32::try_from(r).ok().and_then(|r| l.checked_pow(r)),
+        };
+        value.ok_or(EvalError::Overflow)
+    }
+}
+
+// Unsigned 128 bit integers, what the puzzle answers fit in. Going below zero is
+// an overflow too.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CheckedU128;
+
+impl Backend for CheckedU128 {
+    type Value = u128;
+
+    fn number(&self, digits: &str) -> Result<u128, EvalError> {
+        digits.parse().map_err(|_| EvalError::Overflow)
+    }
+
+    fn neg(&self, v: u128) -> Result<u128, EvalError> {
+        if v == 0 {
+            Ok(0)
+        } else {
+            Err(EvalError::Overflow)
+        }
+    }
+
+    fn apply(&self, op: Opcode, l: u128, r: u128) -> Result<u128, EvalError> {
+        let value = match op {
+            Opcode::Add => l.checked_add(r),
+            Opcode::Sub => l.checked_sub(r),
+            Opcode::Mul => l.checked_mul(r),
+            Opcode::Div | Opcode::Mod if r == 0 => return Err(EvalError::DivisionByZero),
+            Opcode::Div => l.checked_div(r),
+            Opcode::Mod => l.checked_rem(r),
+            Opcode::Pow => u32::try_from(r).ok().and_then(|r| l.checked_pow(r)),
+        };
+        value.ok_or(EvalError::Overflow)
+    }
+}
+
+// A value modulo some modulus, along with the plain integer it stands for
+// while that is still known. Exponents are taken from the integer, since
+// reducing one modulo the modulus would change the power.
+#[derive(Debug, Clone, Copy)]
+pub struct Residue {
+    value: u128,
+    exact: Option<i128>,
+}
+
+impl Residue {
+    // in 0..modulus
+    pub fn value(&self) -> u128 {
+        self.value
+    }
+}
+
+// residues are equal when they are the same modulo the modulus, whatever
+// integers they came from
+impl PartialEq for Residue {
+    fn eq(&self, other: &Residue) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Eq for Residue {}
+
+impl fmt::Display for Residue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+// Arithmetic modulo `modulus`: values live in 0..modulus and wrap around.
+// Division multiplies by the modular inverse; `%` works on the representatives.
+// `^` raises to the exponent as a plain integer, which has to be known: it is
+// lost when an intermediate result overflows an i128 or a division leaves a
+// remainder.
+#[derive(Debug, Clone, Copy)]
+pub struct Modular {
+    modulus: u128,
+}
+
+impl Modular {
+    // None for a zero modulus
+    pub fn new(modulus: u64) -> Option<Modular> {
+        if modulus == 0 {
+            None
+        } else {
+            Some(Modular {
+                modulus: u128::from(modulus),
+            })
+        }
+    }
+
+    pub fn modulus(&self) -> u64 {
+        self.modulus as u64
+    }
+
+    // both operands are below a u64 modulus, so the product fits in a u128
+    fn mul(&self, l: u128, r: u128) -> u128 {
+        l * r % self.modulus
+    }
+
+    fn pow(&self, mut base: u128, mut exponent: u128) -> u128 {
+        let mut result = 1 % self.modulus;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = self.mul(result, base);
+            }
+            base = self.mul(base, base);
+            exponent >>= 1;
+        }
+        result
+    }
+
+    fn inverse(&self, v: u128) -> Result<u128, EvalError> {
+        let (mut old_r, mut r) = (v as i128, self.modulus as i128);
+        let (mut old_s, mut s) = (1i128, 0i128);
+        while r != 0 {
+            let q = old_r / r;
+            let next_r = old_r - q * r;
+            old_r = r;
+            r = next_r;
+            let next_s = old_s - q * s;
+            old_s = s;
+            s = next_s;
+        }
+        if old_r != 1 {
+            return Err(EvalError::NotInvertible);
+        }
+        Ok(old_s.rem_euclid(self.modulus as i128) as u128)
+    }
+}
+
+impl Backend for Modular {
+    type Value = Residue;
+
+    // reduced a digit at a time, so a literal of any length has a residue
+    fn number(&self, digits: &str) -> Result<Residue, EvalError> {
+        let value = digits.bytes().fold(0, |value, d| {
+            (value * 10 + u128::from(d - b'0')) % self.modulus
+        });
+        Ok(Residue {
+            value,
+            exact: digits.parse().ok(),
+        })
+    }
+
+    fn neg(&self, v: Residue) -> Result<Residue, EvalError> {
+        Ok(Residue {
+            value: (self.modulus - v.value) % self.modulus,
+            exact: v.exact.and_then(|n| n.checked_neg()),
+        })
+    }
+
+    fn apply(&self, op: Opcode, l: Residue, r: Residue) -> Result<Residue, EvalError> {
+        let exact = |f: fn(i128, i128) -> Option<i128>| match (l.exact, r.exact) {
+            (Some(a), Some(b)) => f(a, b),
+            _ => None,
+        };
+        let (value, exact) = match op {
+            Opcode::Add => ((l.value + r.value) % self.modulus, exact(i128::checked_add)),
+            Opcode::Sub => (
+                (l.value + self.modulus - r.value) % self.modulus,
+                exact(i128::checked_sub),
+            ),
+            Opcode::Mul => (self.mul(l.value, r.value), exact(i128::checked_mul)),
+            Opcode::Div if r.value == 0 => return Err(EvalError::DivisionByZero),
+            Opcode::Div => (
+                self.mul(l.value, self.inverse(r.value)?),
+                // an exact quotient is the same number as the modular one
+                exact(|a, b| {
+                    if a.checked_rem(b) == Some(0) {
+                        a.checked_div(b)
+                    } else {
+                        None
+                    }
+                }),
+            ),
+            Opcode::Mod if r.value == 0 => return Err(EvalError::DivisionByZero),
+            Opcode::Mod => {
+                let value = l.value % r.value;
+                (value, Some(value as i128))
+            }
+            Opcode::Pow => match r.exact {
+                None => return Err(EvalError::InexactExponent),
+                Some(e) if e < 0 => return Err(EvalError::NegativeExponent),
+                Some(e) => (
+                    self.pow(l.value, e as u128),
+                    l.exact
+                        .zip(u32::try_from(e).ok())
+                        .and_then(|(b, e)| b.checked_pow(e)),
+                ),
+            },
+        };
+        Ok(Residue { value, exact })
+    }
+}
+
+// Arbitrary precision, only limited by memory. Exponents must fit in a u32.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Big;
+
+impl Backend for Big {
+    type Value = BigInt;
+
+    fn number(&self, digits: &str) -> Result<BigInt, EvalError> {
+        digits.parse().map_err(|_| EvalError::Overflow)
+    }
+
+    fn neg(&self, v: BigInt) -> Result<BigInt, EvalError> {
+        Ok(-v)
+    }
+
+    fn apply(&self, op: Opcode, l: BigInt, r: BigInt) -> Result<BigInt, EvalError> {
+        match op {
+            Opcode::Add => Ok(l + r),
+            Opcode::Sub => Ok(l - r),
+            Opcode::Mul => Ok(l * r),
+            Opcode::Div | Opcode::Mod if r.sign() == Sign::NoSign => Err(EvalError::DivisionByZero),
+            Opcode::Div => Ok(l / r),
+            Opcode::Mod => Ok(l % r),
+            Opcode::Pow if r.sign() == Sign::Minus => Err(EvalError::NegativeExponent),
+            Opcode::Pow => match u32::try_from(&r) {
+                Ok(r) => Ok(l.pow(r)),
+                Err(_) => Err(EvalError::ExponentTooLarge),
+            },
+        }
+    }
+}
+
+pub fn calculate(expr: &Expr) -> Result<i128, EvalError> {
+    evaluate(expr, &Int128).map_err(|failure| failure.error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pratt::{self, PrecedenceTable};
+
+    // (expression, left to right, addition first) from the puzzle text
+    const EXAMPLES: [(&str, i128, i128); 6] = [
+        ("1 + 2 * 3 + 4 * 5 + 6", 71, 231),
+        ("1 + (2 * 3) + (4 * (5 + 6))", 51, 51),
+        ("2 * 3 + (4 * 5)", 26, 46),
+        ("5 + (8 * 3 + 9 + 3 * 4 * 3)", 437, 1445),
+        ("5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))", 12240, 669060),
+        (
+            "((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2",
+            13632,
+            23340,
+        ),
+    ];
+
+    // the same for the operators beyond the puzzle's + and *
+    const EXTENDED: [(&str, i128, i128); 5] = [
+        ("10 - 2 * 3 + 1", 25, 32),
+        ("7 - 10 / 2 % 3", -1, -1),
+        ("2 ^ 3 ^ 2 + 1", 65, 513),
+        ("-3 * -(2 + 1)", 9, 9),
+        ("1 - 2 ^ 2 * 3", 3, -9),
+    ];
+
+    #[test]
+    fn puzzle_examples() {
+        let left_to_right = PrecedenceTable::left_to_right();
+        let addition_first = PrecedenceTable::addition_first();
+        for (text, part1, part2) in EXAMPLES.iter().chain(EXTENDED.iter()) {
+            let exp1 = pratt::parse(text, &left_to_right).unwrap();
+            assert_eq!(calculate(&exp1), Ok(*part1), "{}", text);
+            let exp2 = pratt::parse(text, &addition_first).unwrap();
+            assert_eq!(calculate(&exp2), Ok(*part2), "{}", text);
+        }
+        let exp = pratt::parse("7 + 1 / (3 - 3)", &left_to_right).unwrap();
+        assert_eq!(calculate(&exp), Err(EvalError::DivisionByZero));
+    }
+
+    // variables and functions carry over from one line to the next
+    #[test]
+    fn variables_and_functions() {
+        let left_to_right = PrecedenceTable::left_to_right();
+        let addition_first = PrecedenceTable::addition_first();
+        let mut env = Env::new();
+        for line in &["x = 3 + 4", "f(a, b) = a * (b + 1)", "y = f(x, 2) + x"] {
+            let stmt = pratt::parse_stmt(line, &addition_first).unwrap();
+            assert_eq!(execute(stmt, &Int128, &mut env), Ok(None));
+        }
+        assert_eq!(env.var("y"), Some(&28));
+        let exp = pratt::parse("f(y, x) - y", &addition_first).unwrap();
+        assert_eq!(evaluate_in(&exp, &Int128, &env), Ok(196));
+        let stmt = pratt::parse_stmt("f(y, x) - y", &addition_first).unwrap();
+        assert_eq!(execute(stmt, &Int128, &mut env), Ok(Some(196)));
+        let stmt = pratt::parse_stmt("g(n) = 2 * n", &left_to_right).unwrap();
+        execute(stmt, &Int128, &mut env).unwrap();
+        let exp = pratt::parse("g(x) + z", &left_to_right).unwrap();
+        let failure = evaluate_in(&exp, &Int128, &env).unwrap_err();
+        assert_eq!(failure.error, EvalError::UndefinedVariable("z".to_owned()));
+        assert_eq!(failure.expr, "z");
+        assert!(pratt::parse_stmt("1 + x = 2", &left_to_right).is_err());
+    }
+
+    fn modular(modulus: u64, l: u32, op: Opcode, r: u32) -> Result<u128, EvalError> {
+        let backend = Modular::new(modulus).unwrap();
+        let (l, r) = (
+            backend.number(&l.to_string())?,
+            backend.number(&r.to_string())?,
+        );
+        backend.apply(op, l, r).map(|v| v.value())
+    }
+
+    #[test]
+    fn modular_powers_use_the_whole_exponent() {
+        assert_eq!(modular(7, 2, Opcode::Pow, 8), Ok(4));
+        assert_eq!(modular(7, 3, Opcode::Pow, 0), Ok(1));
+        assert_eq!(modular(1, 3, Opcode::Pow, 0), Ok(0));
+        let backend = Modular::new(7).unwrap();
+        let minus_one = backend.neg(backend.number("1").unwrap()).unwrap();
+        let two = backend.number("2").unwrap();
+        assert_eq!(
+            backend.apply(Opcode::Pow, two, minus_one),
+            Err(EvalError::NegativeExponent)
+        );
+    }
+
+    #[test]
+    fn modular_exponents_must_stay_exact() {
+        let backend = Modular::new(11).unwrap();
+        let n = |n: u32| backend.number(&n.to_string()).unwrap();
+        // 8 / 4 divides exactly, 7 / 2 doesn't
+        let even = backend.apply(Opcode::Div, n(8), n(4)).unwrap();
+        assert_eq!(backend.apply(Opcode::Pow, n(3), even), Ok(n(9)));
+        let odd = backend.apply(Opcode::Div, n(7), n(2)).unwrap();
+        assert_eq!(odd, n(9));
+        assert_eq!(
+            backend.apply(Opcode::Pow, n(3), odd),
+            Err(EvalError::InexactExponent)
+        );
+    }
+
+    #[test]
+    fn modular_division_uses_the_inverse() {
+        assert_eq!(modular(11, 7, Opcode::Div, 2), Ok(9));
+        assert_eq!(
+            modular(12, 7, Opcode::Div, 2),
+            Err(EvalError::NotInvertible)
+        );
+        assert_eq!(
+            modular(11, 7, Opcode::Div, 0),
+            Err(EvalError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn literals_are_as_wide_as_the_backend() {
+        let table = crate::pratt::PrecedenceTable::standard();
+        let parse = |text| crate::pratt::parse(text, &table).unwrap();
+        let wide = parse("3000000000 + 1");
+        assert_eq!(evaluate(&wide, &Int128), Ok(3000000001));
+        assert_eq!(evaluate(&wide, &CheckedU128), Ok(3000000001));
+        assert_eq!(evaluate(&wide, &Big), Ok(BigInt::from(3000000001u64)));
+        let modular = Modular::new(7).unwrap();
+        assert_eq!(evaluate(&wide, &modular).unwrap().value(), 3000000001 % 7);
+
+        // 2^127 only fits unsigned, and a 40 digit literal only in a bigint
+        let unsigned = parse("170141183460469231731687303715884105728 - 1");
+        let error = evaluate(&unsigned, &Int128).unwrap_err().error;
+        assert_eq!(error, EvalError::Overflow);
+        assert_eq!(evaluate(&unsigned, &CheckedU128), Ok(i128::MAX as u128));
+        let huge = parse("1234567890123456789012345678901234567890 % 1000");
+        let error = evaluate(&huge, &CheckedU128).unwrap_err().error;
+        assert_eq!(error, EvalError::Overflow);
+        assert_eq!(evaluate(&huge, &Big), Ok(BigInt::from(890)));
+        let residue = evaluate(&parse("1234567890123456789012345678901234567890"), &modular);
+        assert_eq!(residue.unwrap().value(), 3);
+    }
+
+    #[test]
+    fn modular_division_of_the_smallest_i128() {
+        let backend = Modular::new(7).unwrap();
+        let n = |n: u32| backend.number(&n.to_string()).unwrap();
+        let min = backend.apply(Opcode::Pow, backend.neg(n(2)).unwrap(), n(127));
+        let min = min.unwrap();
+        assert_eq!(min.exact, Some(i128::MIN));
+        // i128::MIN / -1 has no exact quotient, but the residue is still right
+        let quotient = backend.apply(Opcode::Div, min, backend.neg(n(1)).unwrap());
+        let quotient = quotient.unwrap();
+        assert_eq!((quotient.value(), quotient.exact), (2, None));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::ast::{Expr, Opcode, Stmt};
+use crate::pratt::{self, Assoc, ParseError, PrecedenceTable};
+
+// Does `child`, printed as the left or `right` operand of `op`, need parentheses
+// to keep its shape under `table`?
+pub fn needs_parens(table: &PrecedenceTable, op: Opcode, child: &Expr, right: bool) -> bool {
+    let inner = match child {
+        Expr::Op(_, inner, _) => *inner,
+        _ => return false,
+    };
+    // At equal precedence the parser hands everything to its right to a
+    // right-associative operator, so a left child needs parentheses when it is
+    // one, and a right child when the parent is left-associative.
+    match (table.get(op), table.get(inner)) {
+        (Some((outer, outer_assoc)), Some((inner, inner_assoc))) => {
+            let (assoc, wrapped) = if right {
+                (outer_assoc, Assoc::Left)
+            } else {
+                (inner_assoc, Assoc::Right)
+            };
+            inner < outer || (inner == outer && assoc == wrapped)
+        }
+        _ => true,
+    }
+}
+
+// What is left to write, last piece first
+enum Piece<'a> {
+    Expr(&'a Expr),
+    Text(&'a str),
+    Op(Opcode),
+}
+
+fn push_operand<'a>(expr: &'a Expr, parens: bool, work: &mut Vec<Piece<'a>>) {
+    if parens {
+        work.push(Piece::Text(")"));
+    }
+    work.push(Piece::Expr(expr));
+    if parens {
+        work.push(Piece::Text("("));
+    }
+}
+
+// Writes with a stack of pieces rather than recursing, so any depth the
+// parser accepts can be written back.
+fn write(expr: &Expr, table: &PrecedenceTable, out: &mut String) {
+    let mut work = vec![Piece::Expr(expr)];
+    while let Some(piece) = work.pop() {
+        let expr = match piece {
+            Piece::Expr(expr) => expr,
+            Piece::Text(text) => {
+                out.push_str(text);
+                continue;
+            }
+            Piece::Op(op) => {
+                out.push_str(&format!(" {:?} ", op));
+                continue;
+            }
+        };
+        match expr {
+            Expr::Number(n) => out.push_str(n),
+            Expr::Var(name) => out.push_str(name),
+            Expr::Call(name, args) => {
+                out.push_str(name);
+                out.push('(');
+                work.push(Piece::Text(")"));
+                for (i, arg) in args.iter().enumerate().rev() {
+                    work.push(Piece::Expr(arg));
+                    if i > 0 {
+                        work.push(Piece::Text(", "));
+                    }
+                }
+            }
+            // unary minus binds tightest, so only an operation under it needs wrapping
+            Expr::Neg(e) => {
+                out.push('-');
+                push_operand(e, matches!(**e, Expr::Op(..)), &mut work);
+            }
+            Expr::Op(l, op, r) => {
+                push_operand(r, needs_parens(table, *op, r, true), &mut work);
+                work.push(Piece::Op(*op));
+                push_operand(l, needs_parens(table, *op, l, false), &mut work);
+            }
+        }
+    }
+}
+
+// `expr` with only the parentheses `table` needs to parse it back into the
+// same tree.
+pub fn format(expr: &Expr, table: &PrecedenceTable) -> String {
+    let mut out = String::new();
+    write(expr, table, &mut out);
+    out
+}
+
+pub fn format_stmt(stmt: &Stmt, table: &PrecedenceTable) -> String {
+    match stmt {
+        Stmt::Expr(e) => format(e, table),
+        Stmt::Assign(name, e) => format!("{} = {}", name, format(e, table)),
+        Stmt::Def(name, params, e) => {
+            format!("{}({}) = {}", name, params.join(", "), format(e, table))
+        }
+    }
+}
+
+// Rewrites a line written for the `from` rules so it means the same under the
+// `to` rules, e.g. left to right homework into school maths:
+// "1 + 2 * 3" becomes "(1 + 2) * 3".
+pub fn convert(
+    line: &str,
+    from: &PrecedenceTable,
+    to: &PrecedenceTable,
+) -> Result<String, ParseError> {
+    let stmt = pratt::parse_stmt(line, from)?;
+    Ok(format_stmt(&stmt, to))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::eval::{calculate, evaluate, Big};
+    use crate::random;
+
+    // formatting with minimal parentheses and parsing back gives the same
+    // tree, under every scheme
+    #[test]
+    fn round_trip() {
+        let schemes: [PrecedenceTable; 4] = [
+            PrecedenceTable::left_to_right(),
+            PrecedenceTable::addition_first(),
+            PrecedenceTable::standard(),
+            "+=3, -=1, *=2:right, /=2, %=4, ^=1".parse().unwrap(),
+        ];
+        let mut rng = StdRng::seed_from_u64(0x2020_0018);
+        for table in schemes.iter() {
+            for _ in 0..500 {
+                let exp = random::expr(&mut rng, 6);
+                let text = format(&exp, table);
+                let back = pratt::parse(&text, table).unwrap();
+                assert_eq!(format!("{:?}", back), format!("{:?}", exp), "{}", text);
+                assert_eq!(evaluate(&back, &Big), evaluate(&exp, &Big));
+            }
+        }
+    }
+
+    #[test]
+    fn minimal_parentheses() {
+        let standard = PrecedenceTable::standard();
+        for (text, formatted) in &[
+            ("((1 + 2)) * (3)", "(1 + 2) * 3"),
+            ("2 ^ (3 ^ 2)", "2 ^ 3 ^ 2"),
+            ("(2 ^ 3) ^ 2", "(2 ^ 3) ^ 2"),
+            ("1 - (2 - 3) - 4", "1 - (2 - 3) - 4"),
+            ("-(x * y) + -(3)", "-(x * y) + -3"),
+            ("f((1), (x + 1) * 2)", "f(1, (x + 1) * 2)"),
+        ] {
+            assert_eq!(
+                convert(text, &standard, &standard),
+                Ok(formatted.to_string())
+            );
+        }
+        let left_to_right = PrecedenceTable::left_to_right();
+        let stmt = pratt::parse_stmt("f(a, b) = a + b * 2", &left_to_right).unwrap();
+        assert_eq!(format_stmt(&stmt, &standard), "f(a, b) = (a + b) * 2");
+    }
+
+    // part one homework rewritten for school maths keeps its value
+    #[test]
+    fn convert_keeps_values() {
+        let left_to_right = PrecedenceTable::left_to_right();
+        let standard = PrecedenceTable::standard();
+        for (text, value) in &[
+            ("1 + 2 * 3 + 4 * 5 + 6", 71),
+            ("5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))", 12240),
+            ("((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2", 13632),
+            ("7 - 10 / 2 % 3", -1),
+            ("2 ^ 3 ^ 2 + 1", 65),
+        ] {
+            let converted = convert(text, &left_to_right, &standard).unwrap();
+            let exp = pratt::parse(&converted, &standard).unwrap();
+            assert_eq!(calculate(&exp), Ok(*value), "{}", converted);
+        }
+        assert_eq!(
+            convert("1 + 2 * 3 + 4 * 5 + 6", &left_to_right, &standard),
+            Ok("((1 + 2) * 3 + 4) * 5 + 6".to_owned())
+        );
+    }
+}
@@ -0,0 +1,14 @@
+pub mod ast;
+pub mod bytecode;
+pub mod diagnostic;
+pub mod eval;
+pub mod format;
+pub mod pratt;
+#[cfg(test)]
+mod random;
+pub mod repl;
+pub mod simplify;
+pub mod steps;
+
+pub use diagnostic::Diagnostic;
+pub use eval::{calculate, evaluate, Backend, EvalError, Failure};
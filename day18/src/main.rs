@@ -62,113 +62,299 @@ Here are the other examples from above:
 What do you get if you add up the results of evaluating the homework problems using these new rules?
 
 */
+use std::env;
+use std::fs;
 use std::io;
 use std::io::prelude::*;
-#[macro_use] extern crate lalrpop_util;
-pub mod ast;
+use std::io::IsTerminal;
+use std::process;
+
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+
+use day18::ast::{Opcode, Stmt};
+use day18::bytecode;
+use day18::eval::{execute, Big, CheckedU128, Env, Int128, Modular};
+use day18::format::format_stmt;
+use day18::pratt::{self, PrecedenceTable};
+use day18::repl::Repl;
+use day18::simplify::simplify_stmt;
+use day18::steps::steps;
+use day18::{Backend, Diagnostic, Failure};
+
+enum Numbers {
+    Int128,
+    CheckedU128,
+    Modular(Modular),
+    Big,
+}
 
-lalrpop_mod!(pub grammar);
-lalrpop_mod!(pub grammar2);
+struct Options {
+    table: Option<PrecedenceTable>,
+    numbers: Numbers,
+    steps: bool,
+    repl: bool,
+    bytecode: bool,
+    simplify: bool,
+    format: Option<PrecedenceTable>,
+}
 
-use ast::{Expr, Opcode};
+fn usage() -> ! {
+    eprintln!("usage: day18 [--precedence <table> | --precedence-file <path>] [--numbers i128|u128|mod:<m>|bigint] [--steps | --repl | --format <table>] [--bytecode] [--simplify]");
+    process::exit(2);
+}
+
+fn table(spec: &str) -> PrecedenceTable {
+    spec.parse().unwrap_or_else(|e| {
+        eprintln!("bad precedence table: {}", e);
+        process::exit(2);
+    })
+}
 
-fn calculate(expr: &Expr) -> u128 {
-    match *expr {
-        Expr::Number(n) => return n as u128,
-        Expr::Op(ref l, op, ref r) => {
-            match op {
-                Opcode::Add => return calculate(&l) + calculate(&r),
-                Opcode::Mul => return calculate(&l) * calculate(&r),
+// --precedence and --format take a table as described on PrecedenceTable::from_str
+fn options() -> Options {
+    let mut options = Options {
+        table: None,
+        numbers: Numbers::Int128,
+        steps: false,
+        repl: false,
+        bytecode: false,
+        simplify: false,
+        format: None,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let flag = match arg.as_str() {
+            "--steps" => Some(&mut options.steps),
+            "--repl" => Some(&mut options.repl),
+            "--bytecode" => Some(&mut options.bytecode),
+            "--simplify" => Some(&mut options.simplify),
+            _ => None,
+        };
+        if let Some(flag) = flag {
+            *flag = true;
+            continue;
+        }
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--precedence" | "--precedence-file" => {
+                let spec = if arg == "--precedence" {
+                    value
+                } else {
+                    fs::read_to_string(&value).unwrap_or_else(|e| {
+                        eprintln!("{}: {}", value, e);
+                        process::exit(1);
+                    })
+                };
+                options.table = Some(table(&spec));
+            }
+            "--format" => options.format = Some(table(&value)),
+            "--numbers" => {
+                options.numbers = match value.as_str() {
+                    "i128" => Numbers::Int128,
+                    "u128" => Numbers::CheckedU128,
+                    "bigint" => Numbers::Big,
+                    m if m.starts_with("mod:") => {
+                        match m[4..].parse().ok().and_then(Modular::new) {
+                            Some(modular) => Numbers::Modular(modular),
+                            None => usage(),
+                        }
+                    }
+                    _ => usage(),
+                }
             }
+            _ => usage(),
+        }
+    }
+    // the rewrites hold for integers, not for wrapping or modular numbers
+    if options.simplify && !matches!(options.numbers, Numbers::Int128 | Numbers::Big) {
+        eprintln!("--simplify works with --numbers i128 or bigint only");
+        process::exit(2);
+    }
+    options
+}
+
+// names and tables of the schemes each line is read under: the given table,
+// or the two from the puzzle
+fn schemes(table: &Option<PrecedenceTable>) -> Vec<(&'static str, PrecedenceTable)> {
+    match table {
+        Some(table) => vec![("custom precedence", table.clone())],
+        None => vec![
+            ("left to right", PrecedenceTable::left_to_right()),
+            ("addition first", PrecedenceTable::addition_first()),
+        ],
+    }
+}
+
+// One statement per precedence scheme. A line that fails to parse under any
+// scheme is rejected as a whole so every total covers the same lines.
+fn parse(
+    line: usize,
+    text: &str,
+    schemes: &[(&str, PrecedenceTable)],
+) -> Result<Vec<Stmt>, Diagnostic> {
+    schemes
+        .iter()
+        .map(|(_, table)| {
+            pratt::parse_stmt(text, table).map_err(|e| Diagnostic::from_pratt(line, text, e))
+        })
+        .collect()
+}
+
+// Prints every line of stdin rewritten for the `to` rules, keeping its value.
+// Lines are read under --precedence, or left to right as in part one, and
+// simplified first with --simplify.
+fn reformat(options: &Options, to: &PrecedenceTable) {
+    let from = options
+        .table
+        .clone()
+        .unwrap_or_else(PrecedenceTable::left_to_right);
+    let stdin = io::stdin();
+    for (i, line) in stdin.lock().lines().enumerate() {
+        let sline = line.unwrap();
+        match pratt::parse_stmt(&sline, &from) {
+            Ok(stmt) if options.simplify => println!("{}", format_stmt(&simplify_stmt(&stmt), to)),
+            Ok(stmt) => println!("{}", format_stmt(&stmt, to)),
+            Err(e) => eprintln!(
+                "{}",
+                Diagnostic::from_pratt(i + 1, &sline, e).render(&sline)
+            ),
+        }
+    }
+}
+
+// Line editing and history on a terminal; piped input is read line by line
+// without prompts so sessions can be scripted.
+fn repl<B: Backend + Clone>(backend: &B, options: &Options) -> io::Result<()> {
+    let mut repl = Repl::new(backend.clone());
+    if let Some(table) = &options.table {
+        repl.set_schemes(vec![("custom".to_owned(), table.clone())]);
+    }
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if !io::stdin().is_terminal() {
+        let stdin = io::stdin();
+        return repl.run_script(stdin.lock(), &mut out);
+    }
+    writeln!(
+        out,
+        "schemes: {} (:help for commands)",
+        repl.scheme_names().join(" | ")
+    )?;
+    let mut editor = Editor::<()>::new();
+    loop {
+        match editor.readline("> ") {
+            Ok(line) => {
+                editor.add_history_entry(line.as_str());
+                if !repl.execute(&line, &mut out)? {
+                    return Ok(());
+                }
+            }
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => return Ok(()),
+            Err(e) => return Err(io::Error::other(e)),
         }
     }
 }
-fn main() {
-    // part 1 grammar check
-    let exp1 = grammar::ExprParser::new().parse("1 + 2 * 3 + 4 * 5 + 6").unwrap();
-    let v1 = calculate(&exp1);
-    //println!("{:?} = {}", exp1, v1);
-    assert_eq!(v1, 71);
-    
-    let exp1 = grammar::ExprParser::new().parse("1 + (2 * 3) + (4 * (5 + 6))").unwrap();
-    let v1 = calculate(&exp1);
-    //println!("{:?} = {}", exp1, v1);
-    assert_eq!(v1, 51);
-
-    let exp1 = grammar::ExprParser::new().parse("2 * 3 + (4 * 5)").unwrap();
-    let v1 = calculate(&exp1);
-    //println!("{:?} = {}", exp1, v1);
-    assert_eq!(v1, 26);
-
-    let exp1 = grammar::ExprParser::new().parse("5 + (8 * 3 + 9 + 3 * 4 * 3)").unwrap();
-    let v1 = calculate(&exp1);
-    //println!("{:?} = {}", exp1, v1);
-    assert_eq!(v1, 437);
-
-    let exp1 = grammar::ExprParser::new().parse("5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))").unwrap();
-    let v1 = calculate(&exp1);
-    //println!("{:?} = {}", exp1, v1);
-    assert_eq!(v1, 12240);
-
-    let exp1 = grammar::ExprParser::new().parse("((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2").unwrap();
-    let v1 = calculate(&exp1);
-    //println!("{:?} = {}", exp1, v1);
-    assert_eq!(v1, 13632);
-
-    // part 2 grammar check
-    let exp1 = grammar2::ExprParser::new().parse("1 + 2 * 3 + 4 * 5 + 6").unwrap();
-    let v1 = calculate(&exp1);
-    //println!("{:?} = {}", exp1, v1);
-    assert_eq!(v1, 231);
-
-    let exp1 = grammar2::ExprParser::new().parse("1 + (2 * 3) + (4 * (5 + 6))").unwrap();
-    let v1 = calculate(&exp1);
-    //println!("{:?} = {}", exp1, v1);
-    assert_eq!(v1, 51);
-
-    let exp1 = grammar2::ExprParser::new().parse("2 * 3 + (4 * 5)").unwrap();
-    let v1 = calculate(&exp1);
-    //println!("{:?} = {}", exp1, v1);
-    assert_eq!(v1, 46);
-
-    let exp1 = grammar2::ExprParser::new().parse("5 + (8 * 3 + 9 + 3 * 4 * 3)").unwrap();
-    let v1 = calculate(&exp1);
-    //println!("{:?} = {}", exp1, v1);
-    assert_eq!(v1, 1445);
-
-    let exp1 = grammar2::ExprParser::new().parse("5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))").unwrap();
-    let v1 = calculate(&exp1);
-    //println!("{:?} = {}", exp1, v1);
-    assert_eq!(v1, 669060);
-
-    let exp1 = grammar2::ExprParser::new().parse("5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))").unwrap();
-    let v1 = calculate(&exp1);
-    //println!("{:?} = {}", exp1, v1);
-    assert_eq!(v1, 669060);
-    
-    let exp1 = grammar2::ExprParser::new().parse("((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2").unwrap();
-    let v1 = calculate(&exp1);
-    //println!("{:?} = {}", exp1, v1);
-    assert_eq!(v1, 23340);
-
-    let mut total1 = 0u128;
-    let mut total2 = 0u128;
+
+// Sums every line of stdin, once per precedence scheme: the given table, or
+// the two puzzle schemes when there is none. Each scheme keeps its own
+// variables and functions, and only bare expressions count toward the totals.
+// --simplify rewrites each line before it runs, for i128 and bigint only.
+// --bytecode runs each line on the stack machine instead of walking the tree.
+// With --steps the reductions of every line are shown instead, and --repl
+// hands stdin to the REPL.
+fn run<B: Backend + Clone>(backend: &B, options: &Options) {
+    if options.repl {
+        if let Err(e) = repl(backend, options) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    let schemes = schemes(&options.table);
+    let zero = backend.number("0").unwrap();
+    let mut totals = vec![zero; schemes.len()];
+    let mut envs: Vec<Env<B::Value>> = schemes.iter().map(|_| Env::new()).collect();
 
     let stdin = io::stdin();
+    for (i, line) in stdin.lock().lines().enumerate() {
+        let sline = line.unwrap();
+        let stmts = match parse(i + 1, &sline, &schemes) {
+            Ok(stmts) => stmts,
+            Err(diagnostic) => {
+                eprintln!("{}", diagnostic.render(&sline));
+                continue;
+            }
+        };
+        let stmts = if options.simplify {
+            stmts.iter().map(simplify_stmt).collect()
+        } else {
+            stmts
+        };
+        if options.steps {
+            for ((stmt, (name, table)), env) in
+                stmts.into_iter().zip(schemes.iter()).zip(envs.iter_mut())
+            {
+                println!("line {}, {}:", i + 1, name);
+                match &stmt {
+                    Stmt::Expr(exp) | Stmt::Assign(_, exp) => match steps(exp, table, backend, env)
+                    {
+                        Ok(lines) => lines.iter().for_each(|line| println!("{}", line)),
+                        Err(failure) => println!("{}", failure),
+                    },
+                    Stmt::Def(name, ..) => println!("defined {}", name),
+                }
+                // any failure was just shown by `steps`; the stack machine
+                // keeps deep lines from overflowing the native stack here too
+                let _ = bytecode::execute(stmt, backend, env);
+                println!();
+            }
+            continue;
+        }
+        let values: Result<Vec<Option<B::Value>>, Failure> = stmts
+            .into_iter()
+            .zip(envs.iter_mut())
+            .map(|(stmt, env)| {
+                if options.bytecode {
+                    bytecode::execute(stmt, backend, env)
+                } else {
+                    execute(stmt, backend, env)
+                }
+            })
+            .collect();
+        match values {
+            Ok(values) => {
+                for (total, v) in totals.iter_mut().zip(values.into_iter().flatten()) {
+                    *total = backend
+                        .apply(Opcode::Add, total.clone(), v)
+                        .unwrap_or_else(|e| {
+                            eprintln!("line {}: total: {}", i + 1, e);
+                            process::exit(1);
+                        });
+                }
+            }
+            Err(failure) => eprintln!("line {}: {}", i + 1, failure),
+        }
+    }
 
-    for line in stdin.lock().lines() {
-        let sline = String::from(line.unwrap().as_str());
-        let exp1 = grammar::ExprParser::new().parse(sline.as_str()).unwrap();
-        let v1 = calculate(&exp1);
-        //println!("{:?} = {}", exp1, v1);
-        let exp2 = grammar2::ExprParser::new().parse(sline.as_str()).unwrap();
-        let v2 = calculate(&exp2);
-        //println!("{:?} = {}", exp2, v2);
-
-        total1 += v1;
-        total2 += v2;
+    if !options.steps {
+        for total in totals {
+            println!("{}", total);
+        }
     }
+}
 
-    println!("{}", total1);
-    println!("{}", total2);
+fn main() {
+    let options = options();
+    if let Some(to) = &options.format {
+        reformat(&options, to);
+        return;
+    }
+    match options.numbers {
+        Numbers::Int128 => run(&Int128, &options),
+        Numbers::CheckedU128 => run(&CheckedU128, &options),
+        Numbers::Modular(modular) => run(&modular, &options),
+        Numbers::Big => run(&Big, &options),
+    }
 }
@@ -0,0 +1,514 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::ast::{Expr, Opcode, Stmt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+// Binding power and associativity per operator. Higher binds tighter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrecedenceTable {
+    ops: HashMap<Opcode, (u8, Assoc)>,
+}
+
+impl PrecedenceTable {
+    pub fn new() -> PrecedenceTable {
+        PrecedenceTable {
+            ops: HashMap::new(),
+        }
+    }
+
+    pub fn with(mut self, op: Opcode, precedence: u8, assoc: Assoc) -> PrecedenceTable {
+        self.ops.insert(op, (precedence, assoc));
+        self
+    }
+
+    pub fn get(&self, op: Opcode) -> Option<(u8, Assoc)> {
+        self.ops.get(&op).copied()
+    }
+
+    // part one: everything left to right, `^` included
+    pub fn left_to_right() -> PrecedenceTable {
+        PrecedenceTable::new()
+            .with(Opcode::Add, 1, Assoc::Left)
+            .with(Opcode::Sub, 1, Assoc::Left)
+            .with(Opcode::Mul, 1, Assoc::Left)
+            .with(Opcode::Div, 1, Assoc::Left)
+            .with(Opcode::Mod, 1, Assoc::Left)
+            .with(Opcode::Pow, 1, Assoc::Left)
+    }
+
+    // part two: addition and subtraction before multiplication and division
+    pub fn addition_first() -> PrecedenceTable {
+        PrecedenceTable::new()
+            .with(Opcode::Add, 2, Assoc::Left)
+            .with(Opcode::Sub, 2, Assoc::Left)
+            .with(Opcode::Mul, 1, Assoc::Left)
+            .with(Opcode::Div, 1, Assoc::Left)
+            .with(Opcode::Mod, 1, Assoc::Left)
+            .with(Opcode::Pow, 3, Assoc::Right)
+    }
+
+    // the rules from school
+    pub fn standard() -> PrecedenceTable {
+        PrecedenceTable::new()
+            .with(Opcode::Add, 1, Assoc::Left)
+            .with(Opcode::Sub, 1, Assoc::Left)
+            .with(Opcode::Mul, 2, Assoc::Left)
+            .with(Opcode::Div, 2, Assoc::Left)
+            .with(Opcode::Mod, 2, Assoc::Left)
+            .with(Opcode::Pow, 3, Assoc::Right)
+    }
+}
+
+impl Default for PrecedenceTable {
+    fn default() -> PrecedenceTable {
+        PrecedenceTable::left_to_right()
+    }
+}
+
+fn opcode(symbol: &str) -> Option<Opcode> {
+    match symbol {
+        "+" => Some(Opcode::Add),
+        "-" => Some(Opcode::Sub),
+        "*" => Some(Opcode::Mul),
+        "/" => Some(Opcode::Div),
+        "%" => Some(Opcode::Mod),
+        "^" => Some(Opcode::Pow),
+        _ => None,
+    }
+}
+
+// Either a preset name (left-to-right, addition-first, standard) or a list of
+// `<op>=<precedence>[:left|:right]` entries separated by commas or newlines,
+// e.g. "+=1, *=2". Lines starting with `#` are comments, so a table can live
+// in a config file.
+impl FromStr for PrecedenceTable {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PrecedenceTable, String> {
+        match s.trim() {
+            "left-to-right" => return Ok(PrecedenceTable::left_to_right()),
+            "addition-first" => return Ok(PrecedenceTable::addition_first()),
+            "standard" => return Ok(PrecedenceTable::standard()),
+            _ => {}
+        }
+        let mut table = PrecedenceTable::new();
+        let entries = s
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(|line| line.split(','))
+            .map(|entry| entry.trim())
+            .filter(|entry| !entry.is_empty());
+        for entry in entries {
+            let (symbol, rest) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected <op>=<precedence>, found {:?}", entry))?;
+            let op = opcode(symbol.trim())
+                .ok_or_else(|| format!("unknown operator {:?}", symbol.trim()))?;
+            let (precedence, assoc) = match rest.split_once(':') {
+                Some((precedence, assoc)) => (precedence, assoc.trim()),
+                None => (rest, "left"),
+            };
+            let precedence = precedence
+                .trim()
+                .parse::<u8>()
+                .map_err(|_| format!("bad precedence {:?}", precedence.trim()))?;
+            let assoc = match assoc {
+                "left" => Assoc::Left,
+                "right" => Assoc::Right,
+                _ => return Err(format!("bad associativity {:?}", assoc)),
+            };
+            table = table.with(op, precedence, assoc);
+        }
+        if table.ops.is_empty() {
+            return Err("empty precedence table".to_owned());
+        }
+        Ok(table)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    // the digits are the token's text
+    Num,
+    // the name is the token's text
+    Ident,
+    Op(Opcode),
+    LParen,
+    RParen,
+    Comma,
+    Assign,
+}
+
+// `start..end` is the byte span of the offending input, empty at the end of it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+    pub expected: Vec<String>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.start)?;
+        if !self.expected.is_empty() {
+            write!(f, ", expected one of {}", self.expected.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token, usize)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        let mut end = start + c.len_utf8();
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            while let Some(&(i, d)) = chars.peek() {
+                if !d.is_ascii_digit() {
+                    break;
+                }
+                end = i + d.len_utf8();
+                chars.next();
+            }
+            tokens.push((start, Token::Num, end));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            while let Some(&(i, d)) = chars.peek() {
+                if !d.is_ascii_alphanumeric() && d != '_' {
+                    break;
+                }
+                end = i + d.len_utf8();
+                chars.next();
+            }
+            tokens.push((start, Token::Ident, end));
+        } else {
+            let token = match c {
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                ',' => Token::Comma,
+                '=' => Token::Assign,
+                _ => match opcode(&input[start..end]) {
+                    Some(op) => Token::Op(op),
+                    None => {
+                        return Err(ParseError {
+                            start,
+                            end,
+                            message: "invalid character".to_owned(),
+                            expected: Vec::new(),
+                        })
+                    }
+                },
+            };
+            tokens.push((start, token, end));
+            chars.next();
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<(usize, Token, usize)>,
+    pos: usize,
+    table: &'a PrecedenceTable,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.pos).map(|&(_, token, _)| token)
+    }
+
+    fn error(&self, expected: &[&str]) -> ParseError {
+        let (start, end, message) = match self.tokens.get(self.pos) {
+            Some(&(start, _, end)) => (
+                start,
+                end,
+                format!("unexpected \"{}\"", &self.input[start..end]),
+            ),
+            None => (
+                self.input.len(),
+                self.input.len(),
+                "unexpected end of line".to_owned(),
+            ),
+        };
+        ParseError {
+            start,
+            end,
+            message,
+            expected: expected.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    // An operand's minus signs and what follows them: a number or a variable
+    // comes back, while an opening parenthesis or call leaves a frame to be
+    // finished once the expression inside it is.
+    fn operand(&mut self, frames: &mut Vec<Frame>, min: &mut u16) -> Result<Box<Expr>, ParseError> {
+        let mut negations = 0;
+        loop {
+            match self.peek() {
+                Some(Token::Num) => {
+                    let (start, _, end) = self.tokens[self.pos];
+                    let digits = self.input[start..end].to_owned();
+                    self.pos += 1;
+                    return Ok(negated(Box::new(Expr::Number(digits)), negations));
+                }
+                Some(Token::Ident) => {
+                    let (start, _, end) = self.tokens[self.pos];
+                    let name = self.input[start..end].to_owned();
+                    self.pos += 1;
+                    if self.peek() != Some(Token::LParen) {
+                        return Ok(negated(Box::new(Expr::Var(name)), negations));
+                    }
+                    self.pos += 1;
+                    if self.peek() == Some(Token::RParen) {
+                        self.pos += 1;
+                        return Ok(negated(Box::new(Expr::Call(name, Vec::new())), negations));
+                    }
+                    frames.push(Frame::Call {
+                        name,
+                        args: Vec::new(),
+                        negations,
+                        min: *min,
+                    });
+                }
+                // unary minus binds tighter than any binary operator
+                Some(Token::Op(Opcode::Sub)) => {
+                    self.pos += 1;
+                    negations += 1;
+                    continue;
+                }
+                Some(Token::LParen) => {
+                    self.pos += 1;
+                    frames.push(Frame::Paren {
+                        negations,
+                        min: *min,
+                    });
+                }
+                _ => return Err(self.error(&["a number", "a name", "\"-\"", "\"(\""])),
+            }
+            negations = 0;
+            *min = 0;
+        }
+    }
+
+    // Precedence climbing: only operators binding at least `min` are folded
+    // into the expression being read, the others wait for it in `frames`.
+    // Nothing recurses, so nesting is only limited by memory.
+    fn expr(&mut self) -> Result<Box<Expr>, ParseError> {
+        let mut frames = Vec::new();
+        let mut min = 0;
+        'operand: loop {
+            let mut lhs = self.operand(&mut frames, &mut min)?;
+            loop {
+                if let Some(Token::Op(op)) = self.peek() {
+                    let (precedence, assoc) = match self.table.get(op) {
+                        Some(entry) => entry,
+                        None => {
+                            let mut error = self.error(&[]);
+                            error.message = format!("operator {:?} has no precedence", op);
+                            return Err(error);
+                        }
+                    };
+                    if u16::from(precedence) >= min {
+                        self.pos += 1;
+                        frames.push(Frame::Rhs { lhs, op, min });
+                        min = match assoc {
+                            Assoc::Left => u16::from(precedence) + 1,
+                            Assoc::Right => u16::from(precedence),
+                        };
+                        continue 'operand;
+                    }
+                }
+                // the expression at `min` is complete
+                match frames.pop() {
+                    None => return Ok(lhs),
+                    Some(Frame::Rhs {
+                        lhs: left,
+                        op,
+                        min: outer,
+                    }) => {
+                        lhs = Box::new(Expr::Op(left, op, lhs));
+                        min = outer;
+                    }
+                    Some(Frame::Paren {
+                        negations,
+                        min: outer,
+                    }) => {
+                        if self.peek() != Some(Token::RParen) {
+                            return Err(self.error(&["an operator", "\")\""]));
+                        }
+                        self.pos += 1;
+                        lhs = negated(lhs, negations);
+                        min = outer;
+                    }
+                    Some(Frame::Call {
+                        name,
+                        mut args,
+                        negations,
+                        min: outer,
+                    }) => {
+                        args.push(*lhs);
+                        match self.peek() {
+                            Some(Token::Comma) => {
+                                self.pos += 1;
+                                frames.push(Frame::Call {
+                                    name,
+                                    args,
+                                    negations,
+                                    min: outer,
+                                });
+                                min = 0;
+                                continue 'operand;
+                            }
+                            Some(Token::RParen) => {
+                                self.pos += 1;
+                                lhs = negated(
+                                    Box::new(Expr::Call(
+                                        name,
+                                        args.into_iter().map(Box::new).collect(),
+                                    )),
+                                    negations,
+                                );
+                                min = outer;
+                            }
+                            _ => return Err(self.error(&["an operator", "\",\"", "\")\""])),
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// What the parser was in the middle of when it started on an inner
+// expression. `min` is what the enclosing expression was read at.
+enum Frame {
+    // `lhs op` waiting for its right operand
+    Rhs {
+        lhs: Box<Expr>,
+        op: Opcode,
+        min: u16,
+    },
+    // waiting for `)`, with the minus signs in front of `(`
+    Paren {
+        negations: usize,
+        min: u16,
+    },
+    // the arguments so far, waiting for `,` or `)`
+    Call {
+        name: String,
+        args: Vec<Expr>,
+        negations: usize,
+        min: u16,
+    },
+}
+
+fn negated(mut expr: Box<Expr>, negations: usize) -> Box<Expr> {
+    for _ in 0..negations {
+        expr = Box::new(Expr::Neg(expr));
+    }
+    expr
+}
+
+pub fn parse(input: &str, table: &PrecedenceTable) -> Result<Box<Expr>, ParseError> {
+    let mut parser = Parser {
+        input,
+        tokens: tokenize(input)?,
+        pos: 0,
+        table,
+    };
+    let expr = parser.expr()?;
+    if parser.peek().is_some() {
+        return Err(parser.error(&["an operator"]));
+    }
+    Ok(expr)
+}
+
+// A whole line: an expression, or `<name> = <expr>` / `<name>(<params>) = <expr>`.
+pub fn parse_stmt(input: &str, table: &PrecedenceTable) -> Result<Stmt, ParseError> {
+    let mut parser = Parser {
+        input,
+        tokens: tokenize(input)?,
+        pos: 0,
+        table,
+    };
+    let expr = parser.expr()?;
+    match parser.peek() {
+        None => Ok(Stmt::Expr(expr)),
+        Some(Token::Assign) => {
+            let (start, end) = (parser.tokens[0].0, parser.tokens[parser.pos - 1].2);
+            parser.pos += 1;
+            let rhs = parser.expr()?;
+            if parser.peek().is_some() {
+                return Err(parser.error(&["an operator"]));
+            }
+            Stmt::assignment(*expr, rhs).ok_or_else(|| ParseError {
+                start,
+                end,
+                message: "can only assign to a variable or f(a, b, ...)".to_owned(),
+                expected: Vec::new(),
+            })
+        }
+        Some(_) => Err(parser.error(&["an operator", "\"=\""])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::format;
+
+    #[test]
+    fn operators_follow_the_table() {
+        let parsed = |text| {
+            format!(
+                "{:?}",
+                parse(text, &PrecedenceTable::addition_first()).unwrap()
+            )
+        };
+        assert_eq!(parsed("1 * 2 + 3"), "(1 * (2 + 3))");
+        assert_eq!(parsed("2 ^ 3 ^ 2 - 1"), "((2 ^ (3 ^ 2)) - 1)");
+        assert_eq!(parsed("-(1 + 2) * -f(x, -3)"), "(-(1 + 2) * -f(x, -3))");
+        assert_eq!(parsed("g() - --4"), "(g() - --4)");
+    }
+
+    #[test]
+    fn errors_point_at_the_token() {
+        let table = PrecedenceTable::standard();
+        let error = parse("f(1 2)", &table).unwrap_err();
+        assert_eq!((error.start, error.end), (4, 5));
+        assert_eq!(error.expected, ["an operator", "\",\"", "\")\""]);
+        let error = parse("(1 + 2", &table).unwrap_err();
+        assert_eq!(
+            (error.start, error.message.as_str()),
+            (6, "unexpected end of line")
+        );
+        let error = parse("1 + * 2", &table).unwrap_err();
+        assert_eq!(error.start, 4);
+    }
+
+    // far deeper than the native stack would allow if parsing, formatting or
+    // dropping recursed
+    #[test]
+    fn deep_nesting() {
+        let depth = 200_000;
+        let table = PrecedenceTable::standard();
+        let nested = format!("{}1{}", "(-".repeat(depth), ")".repeat(depth));
+        let expr = parse(&nested, &table).unwrap();
+        assert_eq!(format(&expr, &table), format!("{}1", "-".repeat(depth)));
+        let powers = vec!["2"; depth].join(" ^ ");
+        let expr = parse(&powers, &table).unwrap();
+        assert_eq!(format(&expr, &table), powers);
+    }
+}
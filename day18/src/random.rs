@@ -0,0 +1,29 @@
+use rand::Rng;
+
+use crate::ast::{Expr, Opcode};
+
+// A random expression at most `depth` operations deep, for property tests.
+// Exponents stay small so the values stay printable.
+pub fn expr<R: Rng>(rng: &mut R, depth: usize) -> Box<Expr> {
+    const OPS: [Opcode; 6] = [
+        Opcode::Add,
+        Opcode::Sub,
+        Opcode::Mul,
+        Opcode::Div,
+        Opcode::Mod,
+        Opcode::Pow,
+    ];
+    if depth == 0 || rng.random_range(0..4) == 0 {
+        return Box::new(Expr::Number(rng.random_range(0..10).to_string()));
+    }
+    if rng.random_range(0..8) == 0 {
+        return Box::new(Expr::Neg(expr(rng, depth - 1)));
+    }
+    let op = OPS[rng.random_range(0..OPS.len())];
+    let r = if op == Opcode::Pow {
+        Box::new(Expr::Number(rng.random_range(0..4).to_string()))
+    } else {
+        expr(rng, depth - 1)
+    };
+    Box::new(Expr::Op(expr(rng, depth - 1), op, r))
+}
@@ -0,0 +1,230 @@
+use std::io;
+use std::io::prelude::*;
+
+use crate::ast::Stmt;
+use crate::diagnostic::Diagnostic;
+use crate::eval::{execute, Backend, Env};
+use crate::pratt::{self, PrecedenceTable};
+use crate::steps::steps;
+
+// A precedence scheme the REPL evaluates under, with the variables and
+// functions defined while it was active.
+struct Scheme<V> {
+    name: String,
+    table: PrecedenceTable,
+    env: Env<V>,
+}
+
+// Evaluates each entry under several precedence schemes at once, the two from
+// the puzzle to begin with.
+pub struct Repl<B: Backend> {
+    backend: B,
+    schemes: Vec<Scheme<B::Value>>,
+    ast: bool,
+    steps: bool,
+    entries: usize,
+}
+
+// A preset name or a table as accepted by PrecedenceTable::from_str
+fn scheme(spec: &str) -> Result<(String, PrecedenceTable), String> {
+    let table = spec.parse()?;
+    Ok((spec.trim().to_owned(), table))
+}
+
+impl<B: Backend> Repl<B> {
+    pub fn new(backend: B) -> Repl<B> {
+        let mut repl = Repl {
+            backend,
+            schemes: Vec::new(),
+            ast: false,
+            steps: false,
+            entries: 0,
+        };
+        repl.set_schemes(vec![
+            ("left-to-right".to_owned(), PrecedenceTable::left_to_right()),
+            (
+                "addition-first".to_owned(),
+                PrecedenceTable::addition_first(),
+            ),
+        ]);
+        repl
+    }
+
+    // Schemes that keep their name keep their variables and functions.
+    pub fn set_schemes(&mut self, schemes: Vec<(String, PrecedenceTable)>) {
+        let mut old = std::mem::take(&mut self.schemes);
+        for (name, table) in schemes {
+            let env = match old.iter().position(|s| s.name == name) {
+                Some(i) => old.swap_remove(i).env,
+                None => Env::new(),
+            };
+            self.schemes.push(Scheme { name, table, env });
+        }
+    }
+
+    pub fn scheme_names(&self) -> Vec<&str> {
+        self.schemes.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    // One statement per scheme, or None once the first parse error is shown
+    fn parse<W: Write>(&mut self, text: &str, out: &mut W) -> io::Result<Option<Vec<Stmt>>> {
+        self.entries += 1;
+        let mut stmts = Vec::new();
+        for scheme in &self.schemes {
+            match pratt::parse_stmt(text, &scheme.table) {
+                Ok(stmt) => stmts.push(stmt),
+                Err(e) => {
+                    let diagnostic = Diagnostic::from_pratt(self.entries, text, e);
+                    writeln!(out, "{}", diagnostic.render(text))?;
+                    return Ok(None);
+                }
+            }
+        }
+        Ok(Some(stmts))
+    }
+
+    // One entry under every scheme. Nothing runs unless it parses under all of
+    // them, like a homework line.
+    fn entry<W: Write>(&mut self, text: &str, out: &mut W) -> io::Result<()> {
+        let stmts = match self.parse(text, out)? {
+            Some(stmts) => stmts,
+            None => return Ok(()),
+        };
+        if self.ast {
+            self.show_ast(&stmts, out)?;
+        }
+        let mut cells = Vec::new();
+        for (stmt, scheme) in stmts.into_iter().zip(self.schemes.iter_mut()) {
+            if self.steps {
+                if let Stmt::Expr(exp) | Stmt::Assign(_, exp) = &stmt {
+                    writeln!(out, "{}:", scheme.name)?;
+                    // a failure shows up in the cell below as well
+                    if let Ok(lines) = steps(exp, &scheme.table, &self.backend, &scheme.env) {
+                        lines
+                            .iter()
+                            .try_for_each(|line| writeln!(out, "{}", line))?;
+                    }
+                }
+            }
+            let (name, defines) = match &stmt {
+                Stmt::Expr(_) => (String::new(), false),
+                Stmt::Assign(name, _) => (name.clone(), false),
+                Stmt::Def(name, ..) => (name.clone(), true),
+            };
+            let cell = match execute(stmt, &self.backend, &mut scheme.env) {
+                Ok(Some(value)) => value.to_string(),
+                Ok(None) if defines => format!("defined {}", name),
+                Ok(None) => format!("{} = {}", name, scheme.env.var(&name).unwrap()),
+                Err(failure) => format!("error: {}", failure),
+            };
+            cells.push(format!("{}: {}", scheme.name, cell));
+        }
+        writeln!(out, "{}", cells.join("   "))
+    }
+
+    fn show_ast<W: Write>(&self, stmts: &[Stmt], out: &mut W) -> io::Result<()> {
+        for (stmt, scheme) in stmts.iter().zip(self.schemes.iter()) {
+            writeln!(out, "{}: {:?}", scheme.name, stmt)?;
+        }
+        Ok(())
+    }
+
+    // Runs one line and writes its output. Returns Ok(false) on quit.
+    //
+    //   <expr> | x = <expr> | f(a, b) = <expr>   evaluate under every scheme
+    //   :ast [expr]       show the parenthesized tree of expr, or toggle
+    //                     showing it for every entry
+    //   :steps            toggle the reduction display
+    //   :mode             list the schemes
+    //   :mode <spec> [| <spec> ...]
+    //                     evaluate under these schemes from now on; a spec is
+    //                     a preset name or a table such as "+=2, *=1"
+    //   :help | :quit | :q
+    pub fn execute<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(true);
+        }
+        if !line.starts_with(':') {
+            self.entry(line, out)?;
+            return Ok(true);
+        }
+        let (command, arg) = match line.split_once(char::is_whitespace) {
+            Some((command, arg)) => (command, arg.trim()),
+            None => (line, ""),
+        };
+        match (command, arg) {
+            (":ast", "") => {
+                self.ast = !self.ast;
+                writeln!(out, "ast {}", if self.ast { "on" } else { "off" })?;
+            }
+            (":ast", text) => {
+                if let Some(stmts) = self.parse(text, out)? {
+                    self.show_ast(&stmts, out)?;
+                }
+            }
+            (":steps", "") => {
+                self.steps = !self.steps;
+                writeln!(out, "steps {}", if self.steps { "on" } else { "off" })?;
+            }
+            (":mode", "") => writeln!(out, "{}", self.scheme_names().join(" | "))?,
+            (":mode", specs) => {
+                let schemes: Result<Vec<_>, String> = specs.split('|').map(scheme).collect();
+                match schemes {
+                    Ok(schemes) => {
+                        self.set_schemes(schemes);
+                        writeln!(out, "{}", self.scheme_names().join(" | "))?;
+                    }
+                    Err(e) => writeln!(out, "bad precedence table: {}", e)?,
+                }
+            }
+            (":help", _) => writeln!(
+                out,
+                "<expr>, x = <expr> or f(a, b) = <expr>; :ast [expr], :steps, :mode [<spec> | ...], :quit"
+            )?,
+            (":quit", "") | (":q", "") => return Ok(false),
+            _ => writeln!(out, "unknown command: {}", line)?,
+        }
+        Ok(true)
+    }
+
+    // Runs `input` one line at a time, as if each were typed in, until it
+    // runs out or a line quits. Only the output of the lines is written, no
+    // prompts, so a session piped in can be compared with a saved one.
+    pub fn run_script<R: BufRead, W: Write>(&mut self, input: R, out: &mut W) -> io::Result<()> {
+        for line in input.lines() {
+            if !self.execute(&line?, out)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Int128;
+
+    fn script(input: &str) -> String {
+        let mut out = Vec::new();
+        Repl::new(Int128)
+            .run_script(input.as_bytes(), &mut out)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn scripts_write_output_only() {
+        assert_eq!(
+            script("x = 2 + 3 * 4\n\n# comment\nx * 2\n"),
+            "left-to-right: x = 20   addition-first: x = 20\n\
+             left-to-right: 40   addition-first: 40\n"
+        );
+    }
+
+    #[test]
+    fn scripts_stop_at_quit() {
+        assert_eq!(script(":mode standard\n:q\n1\n"), "standard\n");
+    }
+}
@@ -0,0 +1,526 @@
+use crate::ast::{Expr, Opcode, Stmt};
+use crate::eval::{Backend, Int128};
+
+// What is left to do, last task first. Each task past `Visit` combines the
+// simplified operands on top of the results.
+enum Task<'a> {
+    Visit(&'a Expr),
+    Neg,
+    Call(&'a str, usize),
+    Op(Opcode),
+    // the chain and whether each of its terms is subtracted
+    Sum(&'a Expr, Vec<bool>),
+    // the chain and how many factors it has
+    Product(&'a Expr, usize),
+}
+
+// Rewrites an expression into a smaller one with the same value: constants
+// are folded, sums and products are flattened so their constants can be
+// combined, and identities such as x + 0, x * 1, x / 1 and x ^ 1 are dropped.
+//
+// The rewrites hold for plain integers, so the result only means the same
+// under Int128 and Big: regrouping a sum can hide an underflow in u128, and
+// `/` folds to a truncated quotient rather than a modular inverse. Folding
+// goes through the same arithmetic as `calculate` and is skipped when it
+// fails, so a division by zero or an overflow still shows up when the result
+// is evaluated. Rules that throw an operand away (x * 0, x ^ 0, x % 1)
+// only do so when it can't fail; variables count as safe, on the assumption
+// that they are defined by the time the expression runs.
+//
+// Works through a stack of tasks rather than recursing, so any depth the
+// parser accepts can be simplified.
+pub fn simplify(expr: &Expr) -> Box<Expr> {
+    let mut results: Vec<Box<Expr>> = Vec::new();
+    let mut work = vec![Task::Visit(expr)];
+    while let Some(task) = work.pop() {
+        match task {
+            Task::Visit(expr) => match expr {
+                Expr::Number(n) => results.push(Box::new(Expr::Number(n.clone()))),
+                Expr::Var(name) => results.push(Box::new(Expr::Var(name.clone()))),
+                Expr::Call(name, args) => {
+                    work.push(Task::Call(name, args.len()));
+                    work.extend(args.iter().rev().map(|arg| Task::Visit(arg)));
+                }
+                Expr::Neg(e) => {
+                    work.push(Task::Neg);
+                    work.push(Task::Visit(e));
+                }
+                Expr::Op(_, Opcode::Add, _) | Expr::Op(_, Opcode::Sub, _) => {
+                    let terms = terms(expr);
+                    work.push(Task::Sum(expr, terms.iter().map(|&(n, _)| n).collect()));
+                    work.extend(terms.into_iter().rev().map(|(_, t)| Task::Visit(t)));
+                }
+                Expr::Op(_, Opcode::Mul, _) => {
+                    let factors = factors(expr);
+                    work.push(Task::Product(expr, factors.len()));
+                    work.extend(factors.into_iter().rev().map(Task::Visit));
+                }
+                Expr::Op(l, op, r) => {
+                    work.push(Task::Op(*op));
+                    work.push(Task::Visit(r));
+                    work.push(Task::Visit(l));
+                }
+            },
+            Task::Neg => {
+                let e = results.pop().unwrap();
+                results.push(negate(e));
+            }
+            Task::Call(name, argc) => {
+                let args = results.split_off(results.len() - argc);
+                results.push(Box::new(Expr::Call(name.to_owned(), args)));
+            }
+            Task::Op(op) => {
+                let r = results.pop().unwrap();
+                let l = results.pop().unwrap();
+                results.push(binary(l, op, r));
+            }
+            Task::Sum(chain, signs) => {
+                let terms = results.split_off(results.len() - signs.len());
+                results.push(sum(chain, signs.into_iter().zip(terms).collect()));
+            }
+            Task::Product(chain, count) => {
+                let factors = results.split_off(results.len() - count);
+                results.push(product(chain, factors.into_iter().map(|f| *f).collect()));
+            }
+        }
+    }
+    results.pop().unwrap()
+}
+
+pub fn simplify_stmt(stmt: &Stmt) -> Stmt {
+    match stmt {
+        Stmt::Expr(e) => Stmt::Expr(simplify(e)),
+        Stmt::Assign(name, e) => Stmt::Assign(name.clone(), simplify(e)),
+        Stmt::Def(name, params, e) => Stmt::Def(name.clone(), params.clone(), simplify(e)),
+    }
+}
+
+fn negate(mut e: Box<Expr>) -> Box<Expr> {
+    match value(&e) {
+        Some(n) => constant(-n),
+        None => match &mut *e {
+            Expr::Neg(inner) => Expr::take(inner),
+            _ => Box::new(Expr::Neg(e)),
+        },
+    }
+}
+
+// any operator but +, - and *, on simplified operands
+fn binary(l: Box<Expr>, op: Opcode, r: Box<Expr>) -> Box<Expr> {
+    if let (Some(a), Some(b)) = (value(&l), value(&r)) {
+        if let Some(folded) = fold(op, a, b) {
+            return folded;
+        }
+    }
+    match (op, value(&r)) {
+        (Opcode::Div, Some(1)) | (Opcode::Pow, Some(1)) => l,
+        (Opcode::Pow, Some(0)) if safe(&l) => constant(1),
+        (Opcode::Mod, Some(1)) if safe(&l) => constant(0),
+        _ => Box::new(Expr::Op(l, op, r)),
+    }
+}
+
+// Negative constants are kept as `-n`, the way the parsers produce them.
+fn constant(n: i128) -> Box<Expr> {
+    let number = Box::new(Expr::Number(n.unsigned_abs().to_string()));
+    if n < 0 {
+        Box::new(Expr::Neg(number))
+    } else {
+        number
+    }
+}
+
+fn value(expr: &Expr) -> Option<i128> {
+    match expr {
+        Expr::Number(n) => n.parse().ok(),
+        Expr::Neg(e) => match &**e {
+            Expr::Number(n) => n.parse::<i128>().ok().map(|n| -n),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// whether `constant(n)` reads back as n under Int128: -n has to be a literal
+fn fits(n: i128) -> bool {
+    n.checked_neg().is_some()
+}
+
+fn fold(op: Opcode, a: i128, b: i128) -> Option<Box<Expr>> {
+    match Int128.apply(op, a, b) {
+        Ok(n) if fits(n) => Some(constant(n)),
+        _ => None,
+    }
+}
+
+// Can evaluating `expr` never fail? Only +, - and * over numbers and
+// variables qualify; overflow is not considered.
+fn safe(expr: &Expr) -> bool {
+    let mut work = vec![expr];
+    while let Some(expr) = work.pop() {
+        match expr {
+            Expr::Number(_) | Expr::Var(_) => {}
+            Expr::Neg(e) => work.push(e),
+            Expr::Op(l, Opcode::Add, r)
+            | Expr::Op(l, Opcode::Sub, r)
+            | Expr::Op(l, Opcode::Mul, r) => {
+                work.push(r);
+                work.push(l);
+            }
+            _ => return false,
+        }
+    }
+    true
+}
+
+fn is_sum(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Op(_, Opcode::Add, _) | Expr::Op(_, Opcode::Sub, _)
+    )
+}
+
+fn is_product(expr: &Expr) -> bool {
+    matches!(expr, Expr::Op(_, Opcode::Mul, _))
+}
+
+// The terms of a chain of + and -, each with whether it is subtracted.
+// Walks the chain with a stack so long homework lines don't recurse.
+fn terms(expr: &Expr) -> Vec<(bool, &Expr)> {
+    let mut terms = Vec::new();
+    let mut work = vec![(false, expr)];
+    while let Some((negative, expr)) = work.pop() {
+        match expr {
+            Expr::Op(l, Opcode::Add, r) => {
+                work.push((negative, r));
+                work.push((negative, l));
+            }
+            Expr::Op(l, Opcode::Sub, r) => {
+                work.push((!negative, r));
+                work.push((negative, l));
+            }
+            _ => terms.push((negative, expr)),
+        }
+    }
+    terms
+}
+
+// a simplified term may itself have turned into a sum or a negation
+fn split_terms(negative: bool, expr: Box<Expr>, out: &mut Vec<(bool, Box<Expr>)>) {
+    let mut work = vec![(negative, expr)];
+    while let Some((negative, mut expr)) = work.pop() {
+        if value(&expr).is_some() {
+            out.push((negative, expr));
+            continue;
+        }
+        match &mut *expr {
+            Expr::Op(l, Opcode::Add, r) => {
+                work.push((negative, Expr::take(r)));
+                work.push((negative, Expr::take(l)));
+            }
+            Expr::Op(l, Opcode::Sub, r) => {
+                work.push((!negative, Expr::take(r)));
+                work.push((negative, Expr::take(l)));
+            }
+            Expr::Neg(e) => work.push((!negative, Expr::take(e))),
+            _ => out.push((negative, expr)),
+        }
+    }
+}
+
+// The constant split_terms would pull out of `terms`, added up in the same
+// order, or None when that overflows.
+fn constant_sum(terms: &[(bool, Box<Expr>)]) -> Option<i128> {
+    let mut total: i128 = 0;
+    let mut work: Vec<(bool, &Expr)> = terms.iter().rev().map(|(n, t)| (*n, &**t)).collect();
+    while let Some((negative, expr)) = work.pop() {
+        if let Some(n) = value(expr) {
+            total = if negative {
+                total.checked_sub(n)?
+            } else {
+                total.checked_add(n)?
+            };
+            continue;
+        }
+        match expr {
+            Expr::Op(l, Opcode::Add, r) => {
+                work.push((negative, r));
+                work.push((negative, l));
+            }
+            Expr::Op(l, Opcode::Sub, r) => {
+                work.push((!negative, r));
+                work.push((negative, l));
+            }
+            Expr::Neg(e) => work.push((!negative, e)),
+            _ => {}
+        }
+    }
+    Some(total)
+}
+
+// `terms` are the simplified terms of `chain`, in order
+fn sum(chain: &Expr, terms: Vec<(bool, Box<Expr>)>) -> Box<Expr> {
+    let total = match constant_sum(&terms) {
+        Some(total) if fits(total) => total,
+        _ => return unflattened(chain, is_sum, terms.into_iter().map(|(_, t)| t)),
+    };
+    let mut split = Vec::new();
+    for (negative, term) in terms {
+        split_terms(negative, term, &mut split);
+    }
+    // terms stay in order, so the first error is still the one reported
+    let mut rest = split.into_iter().filter(|(_, term)| value(term).is_none());
+    let mut result = match rest.next() {
+        None => return constant(total),
+        Some((true, term)) => Box::new(Expr::Neg(term)),
+        Some((false, term)) => term,
+    };
+    for (negative, term) in rest {
+        let op = if negative { Opcode::Sub } else { Opcode::Add };
+        result = Box::new(Expr::Op(result, op, term));
+    }
+    match total {
+        0 => result,
+        n if n < 0 => Box::new(Expr::Op(result, Opcode::Sub, constant(-n))),
+        n => Box::new(Expr::Op(result, Opcode::Add, constant(n))),
+    }
+}
+
+// The factors of a chain of *.
+fn factors(expr: &Expr) -> Vec<&Expr> {
+    let mut factors = Vec::new();
+    let mut work = vec![expr];
+    while let Some(expr) = work.pop() {
+        match expr {
+            Expr::Op(l, Opcode::Mul, r) => {
+                work.push(r);
+                work.push(l);
+            }
+            _ => factors.push(expr),
+        }
+    }
+    factors
+}
+
+// a simplified factor may itself be a product, and the sign of negated
+// factors is pulled out
+fn split_factors(expr: Box<Expr>, negative: &mut bool, out: &mut Vec<Expr>) {
+    let mut work = vec![expr];
+    while let Some(mut expr) = work.pop() {
+        if value(&expr).is_some() {
+            out.push(*expr);
+            continue;
+        }
+        match &mut *expr {
+            Expr::Op(l, Opcode::Mul, r) => {
+                work.push(Expr::take(r));
+                work.push(Expr::take(l));
+            }
+            Expr::Neg(e) => {
+                *negative = !*negative;
+                work.push(Expr::take(e));
+            }
+            _ => out.push(*expr),
+        }
+    }
+}
+
+// The signed constant split_factors would pull out of `factors`, or None
+// when it overflows.
+fn constant_product(factors: &[Expr]) -> Option<i128> {
+    let mut negative = false;
+    let mut coefficient: i128 = 1;
+    let mut work: Vec<&Expr> = factors.iter().rev().collect();
+    while let Some(expr) = work.pop() {
+        if let Some(n) = value(expr) {
+            coefficient = coefficient.checked_mul(n)?;
+            continue;
+        }
+        match expr {
+            Expr::Op(l, Opcode::Mul, r) => {
+                work.push(r);
+                work.push(l);
+            }
+            Expr::Neg(e) => {
+                negative = !negative;
+                work.push(e);
+            }
+            _ => {}
+        }
+    }
+    Some(if negative { -coefficient } else { coefficient })
+}
+
+// `factors` are the simplified factors of `chain`, in order
+fn product(chain: &Expr, factors: Vec<Expr>) -> Box<Expr> {
+    let coefficient = match constant_product(&factors) {
+        Some(c) if fits(c) => c,
+        _ => return unflattened(chain, is_product, factors.into_iter().map(Box::new)),
+    };
+    let mut negative = false;
+    let mut split = Vec::new();
+    for factor in factors {
+        split_factors(Box::new(factor), &mut negative, &mut split);
+    }
+    let rest: Vec<Expr> = split.into_iter().filter(|f| value(f).is_none()).collect();
+    if coefficient == 0 && rest.iter().all(safe) {
+        return constant(0);
+    }
+    // the coefficient leads, as in 6 * x * y
+    let mut factors = rest.into_iter().map(Box::new);
+    let mut result = match (coefficient, factors.next()) {
+        (c, None) => return constant(c),
+        (1, Some(factor)) | (-1, Some(factor)) => factor,
+        (c, Some(factor)) => Box::new(Expr::Op(constant(c), Opcode::Mul, factor)),
+    };
+    for factor in factors {
+        result = Box::new(Expr::Op(result, Opcode::Mul, factor));
+    }
+    if coefficient == -1 {
+        Box::new(Expr::Neg(result))
+    } else {
+        result
+    }
+}
+
+// When the combined constant would not fit in a literal: `chain` keeps its
+// shape, with each operand replaced by its simplified form from `operands`
+fn unflattened<I>(chain: &Expr, link: fn(&Expr) -> bool, mut operands: I) -> Box<Expr>
+where
+    I: Iterator<Item = Box<Expr>>,
+{
+    enum Step<'a> {
+        Visit(&'a Expr),
+        Join(Opcode),
+    }
+    let mut results: Vec<Box<Expr>> = Vec::new();
+    let mut work = vec![Step::Visit(chain)];
+    while let Some(step) = work.pop() {
+        match step {
+            Step::Visit(expr @ Expr::Op(l, op, r)) if link(expr) => {
+                work.push(Step::Join(*op));
+                work.push(Step::Visit(r));
+                work.push(Step::Visit(l));
+            }
+            Step::Visit(_) => results.push(operands.next().unwrap()),
+            Step::Join(op) => {
+                let r = results.pop().unwrap();
+                let l = results.pop().unwrap();
+                results.push(Box::new(Expr::Op(l, op, r)));
+            }
+        }
+    }
+    results.pop().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::eval::{calculate, evaluate, Big, Failure};
+    use crate::format::format;
+    use crate::pratt::{self, PrecedenceTable};
+    use crate::random;
+
+    #[test]
+    fn values_and_errors_are_kept() {
+        let mut rng = StdRng::seed_from_u64(0x2020_0018);
+        let error = |failure: Failure| failure.error;
+        for _ in 0..2000 {
+            let exp = random::expr(&mut rng, 6);
+            let simpler = simplify(&exp);
+            assert_eq!(
+                evaluate(&simpler, &Int128).map_err(error),
+                evaluate(&exp, &Int128).map_err(error),
+                "{:?} became {:?}",
+                exp,
+                simpler
+            );
+            assert_eq!(
+                evaluate(&simpler, &Big).map_err(error),
+                evaluate(&exp, &Big).map_err(error)
+            );
+        }
+    }
+
+    #[test]
+    fn puzzle_examples() {
+        let left_to_right = PrecedenceTable::left_to_right();
+        let addition_first = PrecedenceTable::addition_first();
+        for (text, part1, part2) in &[
+            ("1 + 2 * 3 + 4 * 5 + 6", 71, 231),
+            ("5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))", 12240, 669060),
+            (
+                "((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2",
+                13632,
+                23340,
+            ),
+            ("10 - 2 * 3 + 1", 25, 32),
+            ("-3 * -(2 + 1)", 9, 9),
+        ] {
+            let exp1 = simplify(&pratt::parse(text, &left_to_right).unwrap());
+            assert_eq!(calculate(&exp1), Ok(*part1));
+            let exp2 = simplify(&pratt::parse(text, &addition_first).unwrap());
+            assert_eq!(calculate(&exp2), Ok(*part2));
+        }
+    }
+
+    #[test]
+    fn rewrites() {
+        let standard = PrecedenceTable::standard();
+        for (text, simpler) in &[
+            ("x * 1 + 0 * y + 2 * 3", "x + 6"),
+            ("(x + 3) - (4 - y) * 1", "x + y - 1"),
+            ("2 * x * 3 ^ 1 * -1", "-6 * x"),
+            ("-(-(x)) / 1 + 0 ^ 0", "x + 1"),
+            ("x * 0 + 1 / 0 * 0", "0 * (1 / 0)"),
+        ] {
+            let exp = pratt::parse(text, &standard).unwrap();
+            assert_eq!(format(&simplify(&exp), &standard), *simpler);
+        }
+    }
+
+    #[test]
+    fn wide_constants_keep_the_shape() {
+        let standard = PrecedenceTable::standard();
+        let max = i128::MAX;
+        for text in &[
+            format!("{} + x + 1 - (2 + y)", max),
+            format!("2 * (x * {}) * 3 * -1", max),
+        ] {
+            let exp = pratt::parse(text, &standard).unwrap();
+            let simpler = simplify(&exp);
+            assert_eq!(format(&simpler, &standard), format(&exp, &standard));
+            assert_eq!(
+                evaluate(&simpler, &Int128).map_err(|f| f.error),
+                evaluate(&exp, &Int128).map_err(|f| f.error)
+            );
+        }
+        // the operands still get simplified
+        let exp = pratt::parse(&format!("{} + (x * 1) + 1", max), &standard).unwrap();
+        assert_eq!(
+            format(&simplify(&exp), &standard),
+            format!("{} + x + 1", max)
+        );
+    }
+
+    #[test]
+    fn deep_chains() {
+        let standard = PrecedenceTable::standard();
+        let n = 100_000;
+        let negations = format!("{}x", "-".repeat(n));
+        let exp = pratt::parse(&negations, &standard).unwrap();
+        assert_eq!(format(&simplify(&exp), &standard), "x");
+        let nested = format!("{}x{}", "-(".repeat(n + 1), ")".repeat(n + 1));
+        let exp = pratt::parse(&nested, &standard).unwrap();
+        assert_eq!(format(&simplify(&exp), &standard), "-x");
+        let grouped = format!("{}x{}", "(".repeat(n), " + 1) * 1".repeat(n));
+        let exp = pratt::parse(&grouped, &standard).unwrap();
+        assert_eq!(format(&simplify(&exp), &standard), "x + 100000");
+        let calls = format!("{}x{}", "f(-".repeat(n), ")".repeat(n));
+        let exp = pratt::parse(&calls, &standard).unwrap();
+        assert!(!safe(&simplify(&exp)));
+    }
+}
@@ -0,0 +1,408 @@
+use crate::ast::{Expr, Opcode};
+use crate::eval::{self, Backend, Env, EvalError, Failure};
+use crate::format::needs_parens;
+use crate::pratt::PrecedenceTable;
+
+// The expression as first printed, each node remembering the columns it covers.
+// Nodes sit in one list in evaluation order, which is the order they get
+// reduced in, and refer to their operands by index, so neither laying the
+// expression out nor dropping it recurses however deep it goes.
+struct Node<'a> {
+    expr: &'a Expr,
+    start: usize,
+    end: usize,
+    parens: bool,
+    kind: Kind,
+}
+
+enum Kind {
+    Number,
+    Var,
+    Call(Vec<usize>),
+    Neg(usize),
+    // the left operand, the operator's column and the right operand
+    Op(usize, usize, usize),
+}
+
+enum Visit<'a> {
+    Enter(&'a Expr, bool),
+    Operator(Opcode),
+    Text(&'static str),
+    Exit(&'a Expr, bool, usize),
+}
+
+// `expr` printed under `table` with minimal parentheses, and its nodes
+fn layout<'a>(expr: &'a Expr, table: &PrecedenceTable) -> (String, Vec<Node<'a>>) {
+    let mut text = String::new();
+    let mut nodes: Vec<Node> = Vec::new();
+    // the nodes laid out whose parent isn't yet
+    let mut done: Vec<usize> = Vec::new();
+    let mut columns = Vec::new();
+    let mut work = vec![Visit::Enter(expr, false)];
+    while let Some(visit) = work.pop() {
+        match visit {
+            Visit::Enter(expr, parens) => {
+                work.push(Visit::Exit(expr, parens, text.len()));
+                if parens {
+                    text.push('(');
+                }
+                match expr {
+                    Expr::Number(n) => text.push_str(n),
+                    Expr::Var(name) => text.push_str(name),
+                    Expr::Call(name, args) => {
+                        text.push_str(name);
+                        text.push('(');
+                        for (i, arg) in args.iter().enumerate().rev() {
+                            work.push(Visit::Enter(arg, false));
+                            if i > 0 {
+                                work.push(Visit::Text(", "));
+                            }
+                        }
+                    }
+                    Expr::Neg(e) => {
+                        text.push('-');
+                        work.push(Visit::Enter(e, matches!(**e, Expr::Op(..))));
+                    }
+                    Expr::Op(l, op, r) => {
+                        work.push(Visit::Enter(r, needs_parens(table, *op, r, true)));
+                        work.push(Visit::Operator(*op));
+                        work.push(Visit::Enter(l, needs_parens(table, *op, l, false)));
+                    }
+                }
+            }
+            Visit::Operator(op) => {
+                text.push(' ');
+                columns.push(text.len());
+                text.push_str(&format!("{:?} ", op));
+            }
+            Visit::Text(piece) => text.push_str(piece),
+            Visit::Exit(expr, parens, start) => {
+                let kind = match expr {
+                    Expr::Number(_) => Kind::Number,
+                    Expr::Var(_) => Kind::Var,
+                    Expr::Call(_, args) => {
+                        text.push(')');
+                        Kind::Call(done.split_off(done.len() - args.len()))
+                    }
+                    Expr::Neg(_) => Kind::Neg(done.pop().unwrap()),
+                    Expr::Op(..) => {
+                        let r = done.pop().unwrap();
+                        let l = done.pop().unwrap();
+                        Kind::Op(l, columns.pop().unwrap(), r)
+                    }
+                };
+                if parens {
+                    text.push(')');
+                }
+                done.push(nodes.len());
+                nodes.push(Node {
+                    expr,
+                    start,
+                    end: text.len(),
+                    parens,
+                    kind,
+                });
+            }
+        }
+    }
+    (text, nodes)
+}
+
+fn is_value(node: &Node, values: &[Option<String>], id: usize) -> bool {
+    matches!(node.kind, Kind::Number) || values[id].is_some()
+}
+
+fn ready(nodes: &[Node], values: &[Option<String>], id: usize) -> bool {
+    let operand = |id: usize| is_value(&nodes[id], values, id);
+    match &nodes[id].kind {
+        Kind::Number => false,
+        Kind::Var => true,
+        Kind::Call(args) => args.iter().all(|&arg| operand(arg)),
+        Kind::Neg(e) => operand(*e),
+        Kind::Op(l, _, r) => operand(*l) && operand(*r),
+    }
+}
+
+// unary minus, variables and calls bind tighter than everything
+fn binding(node: &Node, table: &PrecedenceTable) -> u16 {
+    match node.expr {
+        Expr::Op(_, op, _) => table
+            .get(*op)
+            .map_or(0, |(precedence, _)| u16::from(precedence)),
+        _ => u16::MAX,
+    }
+}
+
+// The value of node `id` from those of its operands, which are all known.
+// Literals are read when they are used, so an error in one names it as the
+// tree walker would.
+fn reduce<B: Backend>(
+    nodes: &[Node],
+    id: usize,
+    results: &[Option<B::Value>],
+    backend: &B,
+    env: &Env<B::Value>,
+) -> Result<B::Value, Failure> {
+    let operand = |id: usize| match nodes[id].expr {
+        Expr::Number(digits) => backend
+            .number(digits)
+            .map_err(|error| Failure::new(error, nodes[id].expr)),
+        _ => Ok(results[id].clone().unwrap()),
+    };
+    let expr = nodes[id].expr;
+    let value = match (&nodes[id].kind, expr) {
+        (Kind::Call(args), Expr::Call(name, _)) => {
+            let args = args
+                .iter()
+                .map(|&arg| operand(arg))
+                .collect::<Result<_, _>>()?;
+            return eval::call(expr, name, args, backend, env);
+        }
+        (Kind::Var, Expr::Var(name)) => env
+            .var(name)
+            .cloned()
+            .ok_or_else(|| EvalError::UndefinedVariable(name.clone())),
+        (Kind::Neg(e), _) => backend.neg(operand(*e)?),
+        (Kind::Op(l, _, r), Expr::Op(_, op, _)) => {
+            let (l, r) = (operand(*l)?, operand(*r)?);
+            backend.apply(*op, l, r)
+        }
+        _ => unreachable!("literals are never reduced on their own"),
+    };
+    value.map_err(|error| Failure::new(error, expr))
+}
+
+enum Piece {
+    Node(usize),
+    Text(usize, String),
+}
+
+// pieces of text to place at columns, left to right
+fn draw(nodes: &[Node], values: &[Option<String>], source: &str) -> Vec<(usize, String)> {
+    let mut out = Vec::new();
+    let mut work = vec![Piece::Node(nodes.len() - 1)];
+    while let Some(piece) = work.pop() {
+        let id = match piece {
+            Piece::Text(column, text) => {
+                out.push((column, text));
+                continue;
+            }
+            Piece::Node(id) => id,
+        };
+        let node = &nodes[id];
+        if let Some(value) = &values[id] {
+            let pad = (node.end - node.start).saturating_sub(value.len()) / 2;
+            out.push((node.start + pad, value.clone()));
+            continue;
+        }
+        let inner = node.start + node.parens as usize;
+        let mut pieces = Vec::new();
+        if node.parens {
+            pieces.push(Piece::Text(node.start, "(".to_owned()));
+        }
+        match &node.kind {
+            Kind::Number | Kind::Var => {
+                let to = node.end - node.parens as usize;
+                pieces.push(Piece::Text(inner, source[inner..to].to_owned()));
+            }
+            Kind::Call(args) => {
+                let open = source[inner..].find('(').unwrap() + inner;
+                pieces.push(Piece::Text(inner, source[inner..=open].to_owned()));
+                for (i, &arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        pieces.push(Piece::Text(nodes[args[i - 1]].end, ",".to_owned()));
+                    }
+                    pieces.push(Piece::Node(arg));
+                }
+                let close = node.end - node.parens as usize - 1;
+                pieces.push(Piece::Text(close, ")".to_owned()));
+            }
+            Kind::Neg(e) => {
+                pieces.push(Piece::Text(inner, "-".to_owned()));
+                pieces.push(Piece::Node(*e));
+            }
+            Kind::Op(l, column, r) => {
+                pieces.push(Piece::Node(*l));
+                let op = source[*column..*column + 1].to_owned();
+                pieces.push(Piece::Text(*column, op));
+                pieces.push(Piece::Node(*r));
+            }
+        }
+        if node.parens {
+            pieces.push(Piece::Text(node.end - 1, ")".to_owned()));
+        }
+        work.extend(pieces.into_iter().rev());
+    }
+    out
+}
+
+fn render(pieces: &[(usize, String)]) -> String {
+    let mut line = String::new();
+    for (column, text) in pieces {
+        if line.len() < *column {
+            line.push_str(&" ".repeat(column - line.len()));
+        } else if line.len() > *column && !line.ends_with(' ') {
+            // a value wider than the span it replaced pushes the rest along
+            line.push(' ');
+        }
+        line.push_str(text);
+    }
+    line
+}
+
+// Every line of the puzzle's reduction display for `expr`: the expression as
+// printed under `table` with minimal parentheses, then one line per operation
+// with its result centred under the text it replaces, down to the final value.
+// Results are centred in the span they replace, which matches most but not all
+// of the hand-drawn displays in the puzzle text. Variables and calls are looked
+// up in `env` and replaced by their value in a single step.
+pub fn steps<B: Backend>(
+    expr: &Expr,
+    table: &PrecedenceTable,
+    backend: &B,
+    env: &Env<B::Value>,
+) -> Result<Vec<String>, Failure> {
+    let (source, nodes) = layout(expr, table);
+    let mut pending: Vec<usize> = (0..nodes.len())
+        .filter(|&id| !matches!(nodes[id].kind, Kind::Number))
+        .collect();
+
+    let mut values: Vec<Option<String>> = vec![None; nodes.len()];
+    let mut results: Vec<Option<B::Value>> = vec![None; nodes.len()];
+    let mut lines = vec![source.clone()];
+    while !pending.is_empty() {
+        // like the puzzle: the tightest binding operation that is ready goes
+        // first, the leftmost one on a tie
+        let next = (0..pending.len())
+            .filter(|&i| ready(&nodes, &values, pending[i]))
+            .max_by_key(|&i| {
+                let node = &nodes[pending[i]];
+                (binding(node, table), std::cmp::Reverse(node.start))
+            })
+            .unwrap();
+        let id = pending.remove(next);
+        let value = reduce(&nodes, id, &results, backend, env)?;
+        values[id] = Some(value.to_string());
+        results[id] = Some(value);
+        let line = render(&draw(&nodes, &values, &source));
+        // negating a literal doesn't change what is on screen
+        if lines.last() != Some(&line) {
+            lines.push(line);
+        }
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Int128;
+    use crate::pratt::{parse, parse_stmt};
+
+    fn display(text: &str, table: &PrecedenceTable) -> Result<Vec<String>, Failure> {
+        let expr = parse(text, table).unwrap();
+        steps(&expr, table, &Int128, &Env::new())
+    }
+
+    #[test]
+    fn puzzle_examples() {
+        // the reduction display from part two of the puzzle text
+        let addition_first = PrecedenceTable::addition_first();
+        assert_eq!(
+            display("1 + 2 * 3 + 4 * 5 + 6", &addition_first).unwrap(),
+            [
+                "1 + 2 * 3 + 4 * 5 + 6",
+                "  3   * 3 + 4 * 5 + 6",
+                "  3   *   7   * 5 + 6",
+                "  3   *   7   *  11",
+                "     21       *  11",
+                "         231",
+            ]
+        );
+        // and from part one, with each result centred where the puzzle
+        // lines them up on the left
+        let left_to_right = PrecedenceTable::left_to_right();
+        assert_eq!(
+            display("1 + 2 * 3 + 4 * 5 + 6", &left_to_right).unwrap(),
+            [
+                "1 + 2 * 3 + 4 * 5 + 6",
+                "  3   * 3 + 4 * 5 + 6",
+                "    9     + 4 * 5 + 6",
+                "     13       * 5 + 6",
+                "       65         + 6",
+                "         71",
+            ]
+        );
+    }
+
+    #[test]
+    fn parentheses_and_negation() {
+        let table = PrecedenceTable::standard();
+        assert_eq!(
+            display("-(2 + 3) * (4 - -1)", &table).unwrap(),
+            [
+                "-(2 + 3) * (4 - -1)",
+                "-   5    * (4 - -1)",
+                "   -5    * (4 - -1)",
+                "   -5    *    5",
+                "        -25",
+            ]
+        );
+    }
+
+    #[test]
+    fn variables_and_calls() {
+        let table = PrecedenceTable::standard();
+        let mut env = Env::new();
+        for line in ["x = 3", "f(a, b) = a * b + 1"] {
+            let stmt = parse_stmt(line, &table).unwrap();
+            eval::execute(stmt, &Int128, &mut env).unwrap();
+        }
+        let expr = parse("f(x + 1, 2) - x", &table).unwrap();
+        assert_eq!(
+            steps(&expr, &table, &Int128, &env).unwrap(),
+            [
+                "f(x + 1, 2) - x",
+                "f(3 + 1, 2) - x",
+                "f(3 + 1, 2) - 3",
+                "f(  4  , 2) - 3",
+                "     9      - 3",
+                "       6",
+            ]
+        );
+        let failure = steps(&parse("x + f(1)", &table).unwrap(), &table, &Int128, &env);
+        assert_eq!(
+            failure.unwrap_err().to_string(),
+            "f takes 2 argument(s) but was given 1 in f(1)"
+        );
+        let failure = steps(&parse("x + y", &table).unwrap(), &table, &Int128, &env);
+        assert_eq!(
+            failure.unwrap_err().to_string(),
+            "undefined variable y in y"
+        );
+    }
+
+    #[test]
+    fn errors_name_the_failing_part() {
+        let table = PrecedenceTable::standard();
+        let failure = display("1 + 7 / (3 - 3)", &table).unwrap_err();
+        assert_eq!(failure.to_string(), "division by zero in (7 / (3 - 3))");
+        let failure = display("1 + 170141183460469231731687303715884105728", &table);
+        assert_eq!(failure.unwrap_err().error, EvalError::Overflow);
+    }
+
+    // far deeper than the native stack would allow if laying out or drawing
+    // recursed
+    #[test]
+    fn deep_nesting() {
+        let table = PrecedenceTable::standard();
+        // 2 ^ 2 ^ ... overflows on the fourth power from the right
+        let powers = vec!["2"; 100_000].join(" ^ ");
+        let failure = display(&powers, &table).unwrap_err();
+        assert_eq!(failure.error, EvalError::Overflow);
+        let negations = format!("{}1", "-".repeat(2_000));
+        let lines = display(&negations, &table).unwrap();
+        assert_eq!(lines.first(), Some(&negations));
+        assert_eq!(lines.last().map(|line| line.trim()), Some("1"));
+    }
+}
@@ -0,0 +1,101 @@
+use std::env;
+use std::io::{self, BufReader, Read};
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use day4::{Records, Schema};
+
+// A batch of `bytes` bytes, made up as it is read so it never has to be in
+// memory: random passports with CRLF endings and blank lines that aren't
+// quite empty.
+struct Batch {
+    rng: StdRng,
+    left: u64,
+    pending: Vec<u8>,
+    at: usize,
+}
+
+impl Batch {
+    fn new(bytes: u64) -> Batch {
+        Batch {
+            rng: StdRng::seed_from_u64(0x2020_0004),
+            left: bytes,
+            pending: Vec::new(),
+            at: 0,
+        }
+    }
+
+    fn passport(&mut self) -> Vec<u8> {
+        let rng = &mut self.rng;
+        let unit = if rng.random_bool(0.5) { "cm" } else { "in" };
+        format!(
+            "byr:{} iyr:{} eyr:{}\r\nhgt:{}{} hcl:#{:06x} ecl:brn\r\npid:{:09}\r\n \t\r\n",
+            rng.random_range(1900..2020),
+            rng.random_range(2005..2025),
+            rng.random_range(2015..2035),
+            rng.random_range(50..200),
+            unit,
+            rng.random_range(0..1 << 24),
+            rng.random_range(0..1_000_000_000)
+        )
+        .into_bytes()
+    }
+}
+
+impl Read for Batch {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.at == self.pending.len() {
+            if self.left == 0 {
+                return Ok(0);
+            }
+            self.pending = self.passport();
+            self.at = 0;
+        }
+        let n = buf
+            .len()
+            .min(self.pending.len() - self.at)
+            .min(self.left as usize);
+        buf[..n].copy_from_slice(&self.pending[self.at..self.at + n]);
+        self.at += n;
+        self.left -= n as u64;
+        Ok(n)
+    }
+}
+
+fn main() {
+    let sizes: Vec<u64> = env::args()
+        .skip(1)
+        .filter_map(|arg| arg.parse().ok())
+        .collect();
+    let sizes = if sizes.is_empty() {
+        vec![1 << 20, 1 << 26, 1 << 28]
+    } else {
+        sizes
+    };
+    let schema: Schema = include_str!("../schemas/passport.toml").parse().unwrap();
+    for bytes in sizes {
+        let start = Instant::now();
+        let mut records = Records::new(BufReader::new(Batch::new(bytes)));
+        let mut total = 0;
+        let mut valid = 0;
+        for entry in &mut records {
+            let entry = entry.unwrap();
+            total += 1;
+            if schema.validate(&entry.record).is_ok() {
+                valid += 1;
+            }
+        }
+        assert_eq!(records.offset(), bytes);
+        let elapsed = start.elapsed();
+        println!(
+            "{:>10} bytes: {} records, {} valid, {:?} ({:.0} MB/s)",
+            bytes,
+            total,
+            valid,
+            elapsed,
+            bytes as f64 / elapsed.as_secs_f64() / 1e6
+        );
+    }
+}
@@ -0,0 +1,9 @@
+pub mod reader;
+pub mod record;
+pub mod report;
+pub mod schema;
+
+pub use reader::{Entry, Records};
+pub use record::Record;
+pub use report::{Finding, Report};
+pub use schema::{Field, Problem, Schema, SchemaError};
@@ -117,112 +117,99 @@ iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719
 Count the number of valid passports - those that have all required fields and valid values. Continue to treat cid as optional. In your batch file, how many passports are valid?
 
 */
+use std::env;
+use std::fs;
 use std::io;
-use std::io::prelude::*;
-use regex::Regex;
-use std::collections::HashMap;
-
-fn check(key: &str, value: &str) -> bool {
-    //println!("{} {}", key, value);
-    match key {
-        "byr_" => {
-            let year: i16 = value.parse().unwrap_or(0);
-            //println!(" {}", year);
-            return year >= 1920 && year <= 2002;
-        },
-        "iyr_" => {
-            let year: i16 = value.parse().unwrap_or(0);
-            //println!(" {}", year);
-            return year >= 2010 && year <= 2020;
-        },
-        "eyr_" => {
-            let year: i16 = value.parse().unwrap_or(0);
-            //println!(" {}", year);
-            return year >= 2020 && year <= 2030;
-        },
-        "hgt_cm" => {
-            let len = value.len();
-            let hgt: i16 = value[..len-2].parse().unwrap_or(0);
-            //println!(" {}", hgt);
-            return hgt >= 150 && hgt <= 193;
-        },
-        "hgt_in" => {
-            let len = value.len();
-            let hgt: i16 = value[..len-2].parse().unwrap_or(0);
-            //println!(" {}", hgt);
-            return hgt >= 59 && hgt <= 76;
-        }
-        _ => return true,
-    }
+use std::process;
+
+use day4::{Problem, Records, Report, Schema};
+
+const PASSPORT: &str = include_str!("../schemas/passport.toml");
+
+#[derive(Clone, Copy)]
+enum Format {
+    Text,
+    Json,
+}
+
+fn usage() -> ! {
+    eprintln!("usage: day4 [--report text|json] [schema] < batch");
+    process::exit(2);
 }
+
+// day4 [--report text|json] [schema]: checks the batch on stdin against a
+// schema file, the passport one by default, and prints the bytes read, the
+// records, and how many were valid, missing a field, badly formed, or out of
+// bounds. --report also writes what was wrong with each record, as text or
+// as one JSON object per line.
 fn main() {
+    let mut path = None;
+    let mut format = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--report" => match args.next().as_deref() {
+                Some("text") => format = Some(Format::Text),
+                Some("json") => format = Some(Format::Json),
+                _ => usage(),
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let schema = match path {
+        Some(path) => fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| text.parse::<Schema>().map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| {
+                eprintln!("{}: {}", path, e);
+                process::exit(1);
+            }),
+        None => PASSPORT.parse().unwrap(),
+    };
+
     let stdin = io::stdin();
-    let mut buffer = String::new();
-    let len = stdin.lock().read_to_string(&mut buffer).unwrap();
-    //println!("{}", len);
-    let re = Regex::new(r"((?P<k>\w{3}):(?P<v>\S+))+").unwrap();
-    let mut validate = HashMap::new();
-    validate.insert("byr".to_string(), Regex::new(r"^\d{4}$").unwrap());
-    validate.insert("iyr".to_string(), Regex::new(r"^\d{4}$").unwrap());
-    validate.insert("eyr".to_string(), Regex::new(r"^\d{4}$").unwrap());
-    validate.insert("hgt".to_string(), Regex::new(r"^\d+(cm|in)$").unwrap());
-    validate.insert("hcl".to_string(), Regex::new(r"^#[0-9a-f]{6}$").unwrap());
-    validate.insert("ecl".to_string(), Regex::new(r"^(amb|blu|brn|gry|grn|hzl|oth)$").unwrap());
-    validate.insert("pid".to_string(), Regex::new(r"^\d{9}$").unwrap());
+    let mut records = Records::new(stdin.lock());
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
     let mut count = 0;
-    let mut rejected1 = 0;
-    let mut rejected2 = 0;
-    let mut rejected3 = 0;
+    let mut missing = 0;
+    let mut malformed = 0;
+    let mut out_of_bounds = 0;
     let mut total = 0;
-    for data in buffer.split("\n\n") {
+    for entry in &mut records {
+        let entry = entry.unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+        let report = Report::new(&schema, &entry.record, entry.index, entry.offset);
         total += 1;
-        //println!("\n{:?}", data);
-        let mut set = HashMap::new();
-        for cap in re.captures_iter(data) {
-            //println!(" {} {}", cap["k"].to_string(), cap["v"].to_string());
-            set.insert(cap["k"].to_string(), cap["v"].to_string());
+        match report.findings.first().map(|finding| &finding.problem) {
+            None => count += 1,
+            Some(Problem::Missing) => missing += 1,
+            Some(problem) if problem.is_value_check() => out_of_bounds += 1,
+            Some(_) => malformed += 1,
         }
-        let mut ok = true;
-        for k in ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"].iter() {
-            if ! set.contains_key(&k.to_string()) {
-                rejected1 += 1;
-                ok = false;
-                break;
-            } else {
-                let re = validate.get(&k.to_string()).unwrap();
-                let v = set.get(&k.to_string()).unwrap();
-                let cap = re.captures(v);
-                if cap.is_none() {
-                    if ok != false {
-                        rejected2 += 1;
-                    }
-                    ok = false;
-                    break;
-                } else {
-                    let cap = cap.unwrap();
-                    let group = cap.get(1).map_or("", |m| m.as_str());
-                    if !check([k, group].join("_").as_str(), v) {
-                        if ok != false {
-                            rejected3 += 1;
-                        }
-                        ok = false;
-                        break;
-                    }
-                }
-            }
-        }
-        
-        if ok {
-            count += 1;
-            /*
-            for k in ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"].iter() {
-                print!("{} {}\t", k, v = set.get(&k.to_string()).unwrap());
-            }
-            println!();*/
-            //println!("{:?} {}", set, ok);
+        let written = match format {
+            Some(Format::Text) => report.write_text(&mut out),
+            Some(Format::Json) => report.write_json(&mut out),
+            None => Ok(()),
+        };
+        if let Err(e) = written {
+            eprintln!("{}", e);
+            process::exit(1);
         }
     }
-    println!("{} {} = {} + {} + {} + {}", len, total, count, rejected1, rejected2, rejected3);
-    assert_eq!(total, count + rejected1 + rejected2 + rejected3);
-
+    let len = records.offset();
+    let summary = format!(
+        "{} {} = {} + {} + {} + {}",
+        len, total, count, missing, malformed, out_of_bounds
+    );
+    // the reports have stdout to themselves
+    if format.is_some() {
+        eprintln!("{}", summary);
+    } else {
+        println!("{}", summary);
+    }
+    assert_eq!(total, count + missing + malformed + out_of_bounds);
 }
@@ -0,0 +1,196 @@
+use std::io::{self, BufRead, Read};
+
+use crate::record::Record;
+
+// A record and where it was found in the batch: `index` counts records from 0
+// and `offset` is the byte its first field starts at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub index: usize,
+    pub offset: u64,
+    pub record: Record,
+}
+
+// The most bytes a record can take up, and so any one line, blank or not.
+// Passports are a couple of hundred bytes; past this it isn't a batch of them.
+pub const MAX_RECORD: usize = 16 * 1024;
+
+// Reads a batch one line at a time and yields each record as soon as the line
+// after it is blank. Any line of nothing but whitespace separates records,
+// `\r\n` endings work as well as `\n`, and runs of blank lines don't make
+// empty records. Only the record being read is held in memory, and a record
+// longer than MAX_RECORD is an InvalidData error.
+pub struct Records<R> {
+    reader: R,
+    line: Vec<u8>,
+    offset: u64,
+    index: usize,
+}
+
+impl<R: BufRead> Records<R> {
+    pub fn new(reader: R) -> Records<R> {
+        Records {
+            reader,
+            line: Vec::new(),
+            offset: 0,
+            index: 0,
+        }
+    }
+
+    // how many bytes have been read so far
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+fn too_long(offset: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "record at byte {} is longer than {} bytes",
+            offset, MAX_RECORD
+        ),
+    )
+}
+
+impl<R: BufRead> Iterator for Records<R> {
+    type Item = io::Result<Entry>;
+
+    fn next(&mut self) -> Option<io::Result<Entry>> {
+        let mut record = Record::default();
+        let mut start = None;
+        let mut used = 0;
+        loop {
+            self.line.clear();
+            // one byte more than fits, to tell a line that just fits from one
+            // that goes on
+            let mut reader = (&mut self.reader).take(MAX_RECORD as u64 + 1);
+            let read = match reader.read_until(b'\n', &mut self.line) {
+                Ok(read) => read,
+                Err(e) => return Some(Err(e)),
+            };
+            let line_start = self.offset;
+            self.offset += read as u64;
+            if read == 0 {
+                break;
+            }
+            let text = String::from_utf8_lossy(&self.line);
+            let indent = text.len() - text.trim_start().len();
+            if text.trim().is_empty() {
+                if read > MAX_RECORD {
+                    return Some(Err(too_long(line_start)));
+                }
+                if start.is_some() {
+                    break;
+                }
+                continue;
+            }
+            let offset = *start.get_or_insert(line_start + indent as u64);
+            used += read;
+            if used > MAX_RECORD {
+                return Some(Err(too_long(offset)));
+            }
+            record.push_text(&text);
+        }
+        let offset = start?;
+        let entry = Entry {
+            index: self.index,
+            offset,
+            record,
+        };
+        self.index += 1;
+        Some(Ok(entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Schema;
+
+    const EXAMPLE: &str = "\
+ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
+byr:1937 iyr:2017 cid:147 hgt:183cm
+
+iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884
+hcl:#cfa07d byr:1929
+
+hcl:#ae17e1 iyr:2013
+eyr:2024
+ecl:brn pid:760753108 byr:1931
+hgt:179cm
+
+hcl:#cfa07d eyr:2025 pid:166559648
+iyr:2011 ecl:brn hgt:59in
+";
+
+    fn read(batch: &str) -> Vec<Entry> {
+        Records::new(batch.as_bytes())
+            .map(|entry| entry.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn puzzle_example() {
+        let passport: Schema = include_str!("../schemas/passport.toml").parse().unwrap();
+        let entries = read(EXAMPLE);
+        let offsets: Vec<_> = entries.iter().map(|entry| entry.offset).collect();
+        assert_eq!(offsets, vec![0, 80, 150, 222]);
+        let valid: Vec<bool> = entries
+            .iter()
+            .map(|entry| passport.validate(&entry.record).is_ok())
+            .collect();
+        assert_eq!(valid, vec![true, false, true, false]);
+        let mut records = Records::new(EXAMPLE.as_bytes());
+        assert_eq!(records.by_ref().count(), 4);
+        assert_eq!(records.offset(), EXAMPLE.len() as u64);
+    }
+
+    // CRLF endings, blank lines with spaces in them and runs of them read the
+    // same, with offsets into the batch as it was given
+    #[test]
+    fn separators() {
+        let entries = read("\r\n  \r\n ecl:gry pid:1\r\nbyr:1937\r\n \t\r\n\r\nhgt:59in\r\n");
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].index, entries[0].offset), (0, 7));
+        assert_eq!(entries[0].record, Record::parse("ecl:gry pid:1 byr:1937"));
+        assert_eq!((entries[1].index, entries[1].offset), (1, 38));
+        assert_eq!(entries[1].record.get("hgt"), Some("59in"));
+        // no newline at the end, and nothing but blank lines
+        let entries = read("pid:1\n\npid:2");
+        assert_eq!((entries[1].index, entries[1].offset), (1, 7));
+        assert_eq!(entries[1].record.get("pid"), Some("2"));
+        assert!(Records::new(" \n\r\n\t\n".as_bytes()).next().is_none());
+    }
+
+    #[test]
+    fn records_up_to_the_limit() {
+        let line = format!("pid:{}\n", "1".repeat(MAX_RECORD - 5));
+        let batch = format!("{}\n{}", line, line);
+        let entries: Vec<_> = Records::new(batch.as_bytes())
+            .map(|entry| entry.unwrap())
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].offset, MAX_RECORD as u64 + 1);
+    }
+
+    #[test]
+    fn longer_records_are_errors() {
+        let line = format!("pid:{}\n", "1".repeat(MAX_RECORD / 2));
+        let batch = format!("ecl:brn\n\n{}{}", line, line);
+        let mut records = Records::new(batch.as_bytes());
+        assert_eq!(
+            records.next().unwrap().unwrap().record.get("ecl"),
+            Some("brn")
+        );
+        let error = records.next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            format!("record at byte 9 is longer than {} bytes", MAX_RECORD)
+        );
+        // so are blank lines, or they could grow without bound
+        let blank = " ".repeat(MAX_RECORD + 1);
+        assert!(Records::new(blank.as_bytes()).next().unwrap().is_err());
+    }
+}
@@ -0,0 +1,68 @@
+// One document from a batch: its `key:value` pairs in the order given
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Record {
+    fields: Vec<(String, String)>,
+}
+
+impl Record {
+    // Pairs are separated by any whitespace; anything without a colon is
+    // skipped.
+    pub fn parse(text: &str) -> Record {
+        let mut record = Record::default();
+        record.push_text(text);
+        record
+    }
+
+    // Adds the pairs in `text`, say the next line of the record. A key that is
+    // given again takes the new value in its old place, so a record never
+    // holds more than one value per key.
+    pub fn push_text(&mut self, text: &str) {
+        let pairs = text
+            .split_whitespace()
+            .filter_map(|pair| pair.split_once(':'))
+            .filter(|(key, _)| !key.is_empty());
+        for (key, value) in pairs {
+            match self.fields.iter_mut().find(|(k, _)| k == key) {
+                Some((_, old)) => value.clone_into(old),
+                None => self.fields.push((key.to_owned(), value.to_owned())),
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    // the value of `key`, the last one if it is given twice
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `hgt:` is a height that doesn't match, not a missing one
+    #[test]
+    fn empty_values_are_kept() {
+        let record = Record::parse("hgt: byr:1937 :5 pid");
+        assert_eq!(record.get("hgt"), Some(""));
+        assert_eq!(record.fields().count(), 2);
+    }
+
+    #[test]
+    fn repeated_keys_keep_the_last_value() {
+        let mut record = Record::parse("ecl:brn pid:1 ecl:gry");
+        record.push_text("pid:2\n");
+        let fields: Vec<_> = record.fields().collect();
+        assert_eq!(fields, [("ecl", "gry"), ("pid", "2")]);
+    }
+}
@@ -0,0 +1,200 @@
+use std::io::{self, Write};
+
+use crate::record::Record;
+use crate::schema::{Problem, Schema};
+
+// One field that failed, with the value it had if it had one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding<'s> {
+    pub field: &'s str,
+    pub value: Option<String>,
+    pub problem: Problem,
+}
+
+// What was wrong with one record of a batch, found at `index` and `offset`
+// as in `Entry`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report<'s> {
+    pub index: usize,
+    pub offset: u64,
+    pub findings: Vec<Finding<'s>>,
+}
+
+// `text` as a JSON string
+fn quoted(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl<'s> Report<'s> {
+    pub fn new(schema: &'s Schema, record: &Record, index: usize, offset: u64) -> Report<'s> {
+        let findings = schema
+            .problems(record)
+            .into_iter()
+            .map(|(field, problem)| Finding {
+                field,
+                value: record.get(field).map(|value| value.to_owned()),
+                problem,
+            })
+            .collect();
+        Report {
+            index,
+            offset,
+            findings,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    // `record 3 at byte 120: invalid`, then one indented line per finding
+    pub fn write_text<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let verdict = if self.is_valid() { "valid" } else { "invalid" };
+        writeln!(
+            out,
+            "record {} at byte {}: {}",
+            self.index, self.offset, verdict
+        )?;
+        for finding in &self.findings {
+            match &finding.value {
+                Some(value) => writeln!(
+                    out,
+                    "    {} {:?}: {}",
+                    finding.field, value, finding.problem
+                )?,
+                None => writeln!(out, "    {}: {}", finding.field, finding.problem)?,
+            }
+        }
+        Ok(())
+    }
+
+    // One JSON object on one line, so runs can be diffed line by line
+    pub fn write_json<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(
+            out,
+            "{{\"index\":{},\"offset\":{},\"valid\":{},\"findings\":[",
+            self.index,
+            self.offset,
+            self.is_valid()
+        )?;
+        for (i, finding) in self.findings.iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            let value = finding.value.as_deref().map_or("null".to_owned(), quoted);
+            write!(
+                out,
+                "{{\"field\":{},\"value\":{},\"problem\":\"{}\",\"reason\":{}}}",
+                quoted(finding.field),
+                value,
+                finding.problem.kind(),
+                quoted(&finding.problem.to_string())
+            )?;
+        }
+        writeln!(out, "]}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSPORT: &str = include_str!("../schemas/passport.toml");
+
+    fn text(report: &Report) -> String {
+        let mut out = Vec::new();
+        report.write_text(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn json(report: &Report) -> String {
+        let mut out = Vec::new();
+        report.write_json(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn valid_records() {
+        let passport: Schema = PASSPORT.parse().unwrap();
+        let record =
+            Record::parse("hcl:#cfa07d eyr:2025 pid:166559648 iyr:2011 ecl:brn hgt:59in byr:1950");
+        let report = Report::new(&passport, &record, 3, 7);
+        assert!(report.is_valid());
+        assert_eq!(text(&report), "record 3 at byte 7: valid\n");
+        assert_eq!(
+            json(&report),
+            "{\"index\":3,\"offset\":7,\"valid\":true,\"findings\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn text_report() {
+        let passport: Schema = PASSPORT.parse().unwrap();
+        let record = Record::parse("byr:2003 iyr:2017 hgt:190mm hcl:#zzzzzz pid:1");
+        let report = Report::new(&passport, &record, 4, 120);
+        assert_eq!(
+            text(&report),
+            "record 4 at byte 120: invalid
+    byr \"2003\": 2003 is not between 1920 and 2002
+    eyr: missing
+    hgt \"190mm\": unknown unit \"mm\"
+    hcl \"#zzzzzz\": doesn't match `#[0-9a-f]{6}`
+    ecl: missing
+    pid \"1\": doesn't match `\\d{9}`
+"
+        );
+    }
+
+    #[test]
+    fn json_report() {
+        let passport: Schema = PASSPORT.parse().unwrap();
+        let record = Record::parse("hgt:70in ecl:wat pid:1 byr:1919 iyr:2020 eyr:2020");
+        let report = Report::new(&passport, &record, 0, 0);
+        assert_eq!(
+            json(&report),
+            "{\"index\":0,\"offset\":0,\"valid\":false,\"findings\":[\
+             {\"field\":\"byr\",\"value\":\"1919\",\"problem\":\"out_of_range\",\
+             \"reason\":\"1919 is not between 1920 and 2002\"},\
+             {\"field\":\"hcl\",\"value\":null,\"problem\":\"missing\",\"reason\":\"missing\"},\
+             {\"field\":\"ecl\",\"value\":\"wat\",\"problem\":\"pattern\",\
+             \"reason\":\"doesn't match `amb|blu|brn|gry|grn|hzl|oth`\"},\
+             {\"field\":\"pid\",\"value\":\"1\",\"problem\":\"pattern\",\
+             \"reason\":\"doesn't match `\\\\d{9}`\"}]}\n"
+        );
+    }
+
+    #[test]
+    fn json_escaping() {
+        assert_eq!(quoted("plain"), "\"plain\"");
+        assert_eq!(quoted("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(quoted("back\\slash"), "\"back\\\\slash\"");
+        assert_eq!(
+            quoted("a\nb\tc\u{7}\u{7f}"),
+            "\"a\\nb\\u0009c\\u0007\\u007f\""
+        );
+        // the value as given and the pattern both go through it
+        let schema: Schema = "[[field]]\nname = 'q\"'\npattern = '\\w+'\n"
+            .parse()
+            .unwrap();
+        let record = Record::parse("q\":\"\u{1}\\");
+        let report = Report::new(&schema, &record, 0, 0);
+        assert_eq!(
+            json(&report),
+            "{\"index\":0,\"offset\":0,\"valid\":false,\"findings\":[\
+             {\"field\":\"q\\\"\",\"value\":\"\\\"\\u0001\\\\\",\"problem\":\"pattern\",\
+             \"reason\":\"doesn't match `\\\\w+`\"}]}\n"
+        );
+    }
+}
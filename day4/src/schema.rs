@@ -0,0 +1,415 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::num::{IntErrorKind, ParseIntError};
+use std::str::FromStr;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::record::Record;
+
+// A field as written in a schema file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FieldSpec {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default = "required_by_default")]
+    required: bool,
+    pattern: Option<String>,
+    range: Option<[i64; 2]>,
+    #[serde(default)]
+    units: BTreeMap<String, [i64; 2]>,
+}
+
+fn required_by_default() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SchemaSpec {
+    #[serde(default)]
+    field: Vec<FieldSpec>,
+}
+
+// One field of a document type and what its value has to look like
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub description: String,
+    pub required: bool,
+    // matched against the whole value
+    pub pattern: Option<String>,
+    regex: Option<Regex>,
+    // the value as a number, inclusive
+    pub range: Option<(i64, i64)>,
+    // the number in front of each allowed unit, inclusive
+    pub units: Vec<(String, (i64, i64))>,
+}
+
+// Why a value was turned down
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    Missing,
+    // the pattern, as written in the schema
+    Pattern(String),
+    NotANumber,
+    // the number as written
+    OutOfRange { value: String, min: i64, max: i64 },
+    UnknownUnit(String),
+}
+
+impl Problem {
+    // a short name for the kind of problem, for machines
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Problem::Missing => "missing",
+            Problem::Pattern(_) => "pattern",
+            Problem::NotANumber => "not_a_number",
+            Problem::OutOfRange { .. } => "out_of_range",
+            Problem::UnknownUnit(_) => "unknown_unit",
+        }
+    }
+
+    // Whether the value has the right shape but isn't allowed, as opposed to
+    // being absent or not matching the pattern
+    pub fn is_value_check(&self) -> bool {
+        !matches!(self, Problem::Missing | Problem::Pattern(_))
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Missing => write!(f, "missing"),
+            Problem::Pattern(pattern) => write!(f, "doesn't match `{}`", pattern),
+            Problem::NotANumber => write!(f, "not a number"),
+            Problem::OutOfRange { value, min, max } => {
+                write!(f, "{} is not between {} and {}", value, min, max)
+            }
+            Problem::UnknownUnit(unit) => write!(f, "unknown unit {:?}", unit),
+        }
+    }
+}
+
+// `number` read as an i64 and checked against the bounds; one too long for
+// an i64 is out of range rather than not a number
+fn in_range(number: &str, (min, max): (i64, i64)) -> Result<(), Problem> {
+    let out_of_range = || Problem::OutOfRange {
+        value: number.to_owned(),
+        min,
+        max,
+    };
+    let value: i64 = number.parse().map_err(|e: ParseIntError| match e.kind() {
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => out_of_range(),
+        _ => Problem::NotANumber,
+    })?;
+    if value < min || value > max {
+        return Err(out_of_range());
+    }
+    Ok(())
+}
+
+impl Field {
+    // Checks a value that is present: the pattern first, then the units, then
+    // the range.
+    pub fn check(&self, value: &str) -> Result<(), Problem> {
+        if let (Some(pattern), Some(regex)) = (&self.pattern, &self.regex) {
+            if !regex.is_match(value) {
+                return Err(Problem::Pattern(pattern.clone()));
+            }
+        }
+        if !self.units.is_empty() {
+            let digits = value
+                .find(|c: char| !c.is_ascii_digit() && c != '-')
+                .unwrap_or(value.len());
+            let (number, unit) = value.split_at(digits);
+            let bounds = self
+                .units
+                .iter()
+                .find(|(name, _)| name == unit)
+                .ok_or_else(|| Problem::UnknownUnit(unit.to_owned()))?
+                .1;
+            in_range(number, bounds)?;
+        }
+        if let Some(bounds) = self.range {
+            in_range(value, bounds)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum SchemaError {
+    Toml(toml::de::Error),
+    Pattern { field: String, error: regex::Error },
+    // min above max
+    BadRange { field: String },
+    DuplicateField(String),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaError::Toml(error) => write!(f, "{}", error),
+            SchemaError::Pattern { field, error } => {
+                write!(f, "field {:?}: bad pattern: {}", field, error)
+            }
+            SchemaError::BadRange { field } => {
+                write!(f, "field {:?}: range goes from high to low", field)
+            }
+            SchemaError::DuplicateField(field) => write!(f, "field {:?} listed twice", field),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+// The fields a document type has, read from TOML with one `[[field]]` table
+// per field, in the order they are checked. Fields that aren't listed are
+// ignored.
+#[derive(Debug, Clone)]
+pub struct Schema {
+    fields: Vec<Field>,
+}
+
+impl Schema {
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    // Everything wrong with `record`, one problem per field, in field order.
+    pub fn problems<'s>(&'s self, record: &Record) -> Vec<(&'s str, Problem)> {
+        self.fields
+            .iter()
+            .filter_map(|field| {
+                let result = match record.get(&field.name) {
+                    Some(value) => field.check(value),
+                    None if field.required => Err(Problem::Missing),
+                    None => Ok(()),
+                };
+                result.err().map(|problem| (field.name.as_str(), problem))
+            })
+            .collect()
+    }
+
+    // The first thing wrong with `record`, going through the fields in order.
+    pub fn validate<'s>(&'s self, record: &Record) -> Result<(), (&'s str, Problem)> {
+        match self.problems(record).into_iter().next() {
+            Some(problem) => Err(problem),
+            None => Ok(()),
+        }
+    }
+}
+
+fn bounds(field: &str, [min, max]: [i64; 2]) -> Result<(i64, i64), SchemaError> {
+    if min > max {
+        return Err(SchemaError::BadRange {
+            field: field.to_owned(),
+        });
+    }
+    Ok((min, max))
+}
+
+impl FromStr for Schema {
+    type Err = SchemaError;
+
+    fn from_str(s: &str) -> Result<Schema, SchemaError> {
+        let spec: SchemaSpec = toml::from_str(s).map_err(SchemaError::Toml)?;
+        let mut fields: Vec<Field> = Vec::new();
+        for spec in spec.field {
+            if fields.iter().any(|field| field.name == spec.name) {
+                return Err(SchemaError::DuplicateField(spec.name));
+            }
+            let regex = match &spec.pattern {
+                Some(pattern) => {
+                    Some(Regex::new(&format!("^(?:{})$", pattern)).map_err(|error| {
+                        SchemaError::Pattern {
+                            field: spec.name.clone(),
+                            error,
+                        }
+                    })?)
+                }
+                None => None,
+            };
+            let range = match spec.range {
+                Some(range) => Some(bounds(&spec.name, range)?),
+                None => None,
+            };
+            let name = &spec.name;
+            let units = spec
+                .units
+                .into_iter()
+                .map(|(unit, range)| Ok((unit, bounds(name, range)?)))
+                .collect::<Result<_, _>>()?;
+            fields.push(Field {
+                name: spec.name,
+                description: spec.description,
+                required: spec.required,
+                pattern: spec.pattern,
+                regex,
+                range,
+                units,
+            });
+        }
+        Ok(Schema { fields })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSPORT: &str = include_str!("../schemas/passport.toml");
+
+    fn passport() -> Schema {
+        PASSPORT.parse().unwrap()
+    }
+
+    fn out_of_range(value: &str, min: i64, max: i64) -> Problem {
+        Problem::OutOfRange {
+            value: value.to_owned(),
+            min,
+            max,
+        }
+    }
+
+    fn error(schema: &str) -> String {
+        schema.parse::<Schema>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn passport_fields() {
+        let passport = passport();
+        let check = |field: &str, value: &str| passport.field(field).unwrap().check(value);
+        assert_eq!(check("byr", "2002"), Ok(()));
+        assert_eq!(check("byr", "2003"), Err(out_of_range("2003", 1920, 2002)));
+        assert_eq!(check("hcl", "#123abc"), Ok(()));
+        assert!(check("hcl", "#123abz").is_err());
+        assert!(check("hcl", "123abc").is_err());
+        assert_eq!(check("ecl", "brn"), Ok(()));
+        assert!(check("ecl", "wat").is_err());
+        assert_eq!(check("pid", "000000001"), Ok(()));
+        assert_eq!(
+            check("pid", "0123456789"),
+            Err(Problem::Pattern("\\d{9}".to_owned()))
+        );
+    }
+
+    #[test]
+    fn units() {
+        let passport = passport();
+        let hgt = passport.field("hgt").unwrap();
+        assert_eq!(hgt.check("60in"), Ok(()));
+        assert_eq!(hgt.check("190cm"), Ok(()));
+        assert_eq!(hgt.check("190in"), Err(out_of_range("190", 59, 76)));
+        assert_eq!(
+            hgt.check("190"),
+            Err(Problem::Pattern("\\d+[a-z]+".to_owned()))
+        );
+        assert_eq!(
+            hgt.check("190mm"),
+            Err(Problem::UnknownUnit("mm".to_owned()))
+        );
+        // a number too long for an i64 is still out of range
+        assert_eq!(
+            hgt.check("99999999999999999999cm"),
+            Err(out_of_range("99999999999999999999", 150, 193))
+        );
+    }
+
+    #[test]
+    fn signed_units() {
+        // with no pattern, a leading `-` belongs to the number, one anywhere
+        // else makes it not a number
+        let schema: Schema = "\
+[[field]]
+name = \"temp\"
+units = { c = [-40, 40], f = [-40, 104] }
+"
+        .parse()
+        .unwrap();
+        let temp = schema.field("temp").unwrap();
+        assert_eq!(temp.check("-40c"), Ok(()));
+        assert_eq!(temp.check("-41f"), Err(out_of_range("-41", -40, 104)));
+        assert_eq!(temp.check("4-1c"), Err(Problem::NotANumber));
+        assert_eq!(temp.check("-c"), Err(Problem::NotANumber));
+        assert_eq!(temp.check("40"), Err(Problem::UnknownUnit("".to_owned())));
+        assert_eq!(
+            temp.check("-99999999999999999999c"),
+            Err(out_of_range("-99999999999999999999", -40, 40))
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let schema: Schema = "[[field]]\nname = \"n\"\nrange = [-5, 5]\n"
+            .parse()
+            .unwrap();
+        let n = schema.field("n").unwrap();
+        assert_eq!(n.check("-5"), Ok(()));
+        assert_eq!(n.check("6"), Err(out_of_range("6", -5, 5)));
+        assert_eq!(n.check("five"), Err(Problem::NotANumber));
+        assert_eq!(
+            n.check("-9223372036854775809"),
+            Err(out_of_range("-9223372036854775809", -5, 5))
+        );
+    }
+
+    #[test]
+    fn optional_fields() {
+        let passport = passport();
+        let record =
+            Record::parse("byr:1937 iyr:2017 eyr:2020 hgt:183cm hcl:#fffffd ecl:gry pid:860033327");
+        assert!(!passport.field("cid").unwrap().required);
+        assert_eq!(passport.validate(&record), Ok(()));
+        assert_eq!(
+            passport.validate(&Record::parse("byr:1937 iyr:2017")),
+            Err(("eyr", Problem::Missing))
+        );
+        let schema: Schema = "\
+[[field]]
+name = \"a\"
+required = false
+pattern = 'x+'
+"
+        .parse()
+        .unwrap();
+        assert_eq!(schema.validate(&Record::default()), Ok(()));
+        // an optional field that is there still has to be right
+        assert_eq!(
+            schema.validate(&Record::parse("a:y")),
+            Err(("a", Problem::Pattern("x+".to_owned())))
+        );
+    }
+
+    #[test]
+    fn schema_errors() {
+        assert_eq!(
+            error("[[field]]\nname = \"x\"\nrange = [2, 1]\n"),
+            "field \"x\": range goes from high to low"
+        );
+        assert_eq!(
+            error("[[field]]\nname = \"x\"\nunits = { cm = [2, 1] }\n"),
+            "field \"x\": range goes from high to low"
+        );
+        assert_eq!(
+            error("[[field]]\nname = \"x\"\n\n[[field]]\nname = \"x\"\n"),
+            "field \"x\" listed twice"
+        );
+        assert!(matches!(
+            "[[field]]\nname = \"x\"\npattern = '[a-'\n".parse::<Schema>(),
+            Err(SchemaError::Pattern { field, .. }) if field == "x"
+        ));
+        assert!(matches!(
+            "[[field]]\nname = \"x\"\ncolor = \"red\"\n".parse::<Schema>(),
+            Err(SchemaError::Toml(_))
+        ));
+    }
+}
@@ -0,0 +1,74 @@
+use std::env;
+use std::fmt::Write;
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use day7::Graph;
+
+// Rules for `colors` colors where each bag holds the next color and up to
+// three more that come after it, so the rules never go round in a circle.
+fn generate(colors: usize, seed: u64) -> String {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut rules = String::new();
+    for i in 0..colors {
+        write!(rules, "shade{} bag bags contain ", i).unwrap();
+        let remaining = colors - i - 1;
+        if remaining == 0 {
+            rules.push_str("no other bags.\n");
+            continue;
+        }
+        let count = rng.random_range(1..=4.min(remaining));
+        for k in 0..count {
+            let inner = if k == 0 {
+                i + 1
+            } else {
+                i + 1 + rng.random_range(0..remaining.min(50))
+            };
+            let quantity = rng.random_range(1..=5);
+            let sep = if k + 1 == count { ".\n" } else { ", " };
+            write!(rules, "{} shade{} bag bags{}", quantity, inner, sep).unwrap();
+        }
+    }
+    rules
+}
+
+fn main() {
+    let sizes: Vec<usize> = env::args()
+        .skip(1)
+        .filter_map(|arg| arg.parse().ok())
+        .collect();
+    let sizes = if sizes.is_empty() {
+        vec![1_000, 10_000, 50_000]
+    } else {
+        sizes
+    };
+    for colors in sizes {
+        let graph: Graph = generate(colors, 0x2020_0007 ^ colors as u64)
+            .parse()
+            .unwrap();
+        let target = format!("shade{} bag", colors / 2);
+
+        let start = Instant::now();
+        let fast = graph.containers_of(&target).unwrap();
+        let fast_time = start.elapsed();
+
+        // the quadratic search takes minutes past this size
+        let slow_time = if colors <= 10_000 {
+            let start = Instant::now();
+            let slow = graph.brute_force_containers_of(&target).unwrap();
+            assert_eq!(fast, slow);
+            format!("{:?}", start.elapsed())
+        } else {
+            "skipped".to_owned()
+        };
+        println!(
+            "{:>8} colors: {} containers, reverse index {:?}, search from every color {}",
+            colors,
+            fast.len(),
+            fast_time,
+            slow_time
+        );
+    }
+}
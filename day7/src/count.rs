@@ -0,0 +1,121 @@
+use num_bigint::BigUint;
+
+// A number of bags. `total_contents` can count in any of these; the fixed
+// width ones report overflow instead of wrapping.
+pub trait Count: Clone {
+    fn zero() -> Self;
+
+    // self + quantity * (1 + inner): the bags added by one line of a rule.
+    // None when the result doesn't fit.
+    fn add_bags(&self, quantity: u32, inner: &Self) -> Option<Self>;
+}
+
+impl Count for u64 {
+    fn zero() -> u64 {
+        0
+    }
+
+    fn add_bags(&self, quantity: u32, inner: &u64) -> Option<u64> {
+        inner
+            .checked_add(1)?
+            .checked_mul(u64::from(quantity))?
+            .checked_add(*self)
+    }
+}
+
+impl Count for u128 {
+    fn zero() -> u128 {
+        0
+    }
+
+    fn add_bags(&self, quantity: u32, inner: &u128) -> Option<u128> {
+        inner
+            .checked_add(1)?
+            .checked_mul(u128::from(quantity))?
+            .checked_add(*self)
+    }
+}
+
+impl Count for BigUint {
+    fn zero() -> BigUint {
+        BigUint::from(0u32)
+    }
+
+    fn add_bags(&self, quantity: u32, inner: &BigUint) -> Option<BigUint> {
+        Some(self + (inner + 1u32) * quantity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::graph::Graph;
+
+    #[test]
+    fn add_bags() {
+        assert_eq!(5u64.add_bags(2, &3), Some(13));
+        assert_eq!(0u64.add_bags(1, &u64::MAX), None);
+        assert_eq!(u64::MAX.add_bags(1, &0), None);
+        assert_eq!(0u128.add_bags(2, &u128::from(u64::MAX)), Some(1 << 65));
+        assert_eq!(
+            BigUint::from(5u32).add_bags(2, &BigUint::from(3u32)),
+            Some(BigUint::from(13u32))
+        );
+    }
+
+    #[test]
+    fn cycles() {
+        // rules that go round in a circle are reported instead of looping forever
+        let cyclic: Graph =
+            "a b bags contain 1 c d bag.\nc d bags contain 2 e f bags.\ne f bags contain 1 a b bag.\n"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            cyclic.total_contents("a b").map_err(|e| e.to_string()),
+            Err("bags contain themselves: a b -> c d -> e f -> a b".to_owned())
+        );
+        assert_eq!(
+            cyclic.count_contents::<BigUint>("e f"),
+            Err(Error::Cycle(vec![
+                "e f".to_owned(),
+                "a b".to_owned(),
+                "c d".to_owned()
+            ]))
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        // 20 levels of 1000 bags don't fit in a u64, but do in a u128 or a
+        // bigint; the first u64 count to overflow is the one 7 levels up from
+        // the bottom
+        let mut deep = String::new();
+        for level in 0..20 {
+            deep.push_str(&format!(
+                "level {} bags contain 1000 level {} bags.\n",
+                level,
+                level + 1
+            ));
+        }
+        let deep: Graph = deep.parse().unwrap();
+        assert_eq!(
+            deep.total_contents("level 0"),
+            Err(Error::Overflow("level 13".to_owned()))
+        );
+        assert_eq!(
+            deep.total_contents("level 14"),
+            Ok(1_001_001_001_001_001_000)
+        );
+        let expected = (1..=20)
+            .map(|n| BigUint::from(1000u32).pow(n))
+            .sum::<BigUint>();
+        assert_eq!(deep.count_contents::<BigUint>("level 0"), Ok(expected));
+        let expected: u128 = (1..=12).map(|n| 1000u128.pow(n)).sum();
+        assert_eq!(deep.count_contents::<u128>("level 8"), Ok(expected));
+        assert_eq!(
+            deep.count_contents::<u128>("level 0"),
+            Err(Error::Overflow("level 7".to_owned()))
+        );
+    }
+}
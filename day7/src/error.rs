@@ -0,0 +1,81 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    // the line isn't `<color> bags contain <contents>.`
+    Syntax(String),
+    // one entry of the contents isn't `<n> <color> bag(s)`
+    BadContent(String),
+    BadQuantity(String),
+    DuplicateRule(String),
+    // a line of a weights file isn't `<color>: <weight>`
+    WeightSyntax(String),
+    BadWeight(String),
+    DuplicateWeight(String),
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::Syntax(text) => write!(
+                f,
+                "expected `<color> bags contain <contents>.`, found {:?}",
+                text
+            ),
+            ParseErrorKind::BadContent(text) => {
+                write!(f, "expected `<n> <color> bag(s)`, found {:?}", text)
+            }
+            ParseErrorKind::BadQuantity(text) => write!(f, "bad quantity {:?}", text),
+            ParseErrorKind::DuplicateRule(color) => {
+                write!(f, "second rule for {:?}", color)
+            }
+            ParseErrorKind::WeightSyntax(text) => {
+                write!(f, "expected `<color>: <weight>`, found {:?}", text)
+            }
+            ParseErrorKind::BadWeight(text) => write!(f, "bad weight {:?}", text),
+            ParseErrorKind::DuplicateWeight(color) => {
+                write!(f, "second weight for {:?}", color)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Parse { line: usize, kind: ParseErrorKind },
+    UnknownColor(String),
+    // colors that end up inside themselves, each holding the next and the
+    // last holding the first
+    Cycle(Vec<String>),
+    // the color whose count didn't fit
+    Overflow(String),
+    // a color that can go in the bag being packed but has no weight
+    MissingWeight(String),
+    // the color whose packing would need too much memory at that capacity
+    TooLarge(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse { line, kind } => write!(f, "line {}: {}", line, kind),
+            Error::UnknownColor(color) => write!(f, "no bag is colored {:?}", color),
+            Error::Cycle(colors) => {
+                write!(f, "bags contain themselves: ")?;
+                for color in colors {
+                    write!(f, "{} -> ", color)?;
+                }
+                write!(f, "{}", colors[0])
+            }
+            Error::Overflow(color) => write!(f, "too many bags inside {:?}", color),
+            Error::MissingWeight(color) => write!(f, "no weight given for {:?}", color),
+            Error::TooLarge(color) => write!(
+                f,
+                "packing {:?} up to that capacity takes too much memory",
+                color
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
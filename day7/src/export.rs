@@ -0,0 +1,193 @@
+use std::io::{self, Write};
+
+use crate::graph::Graph;
+
+// `text` as a double-quoted string, good for both DOT and JSON
+fn quoted(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl Graph {
+    // The rules as a Graphviz digraph: one box per color, in the order they
+    // were first seen, and an arrow to each color it holds labelled with how
+    // many.
+    pub fn write_dot<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "digraph bags {{")?;
+        writeln!(out, "    node [shape=box, fontname=monospace];")?;
+        for id in 0..self.len() {
+            writeln!(out, "    n{} [label={}];", id, quoted(self.name(id)))?;
+        }
+        for id in 0..self.len() {
+            for &(inner, quantity) in self.children(id) {
+                writeln!(out, "    n{} -> n{} [label=\"{}\"];", id, inner, quantity)?;
+            }
+        }
+        writeln!(out, "}}")
+    }
+
+    // The rules as a JSON object from each color to the bags it holds
+    // directly, one color per line:
+    // `"light red": [{"color": "bright white", "quantity": 1}, ...]`
+    pub fn write_json<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "{{")?;
+        for id in 0..self.len() {
+            write!(out, "  {}: [", quoted(self.name(id)))?;
+            for (i, &(inner, quantity)) in self.children(id).iter().enumerate() {
+                if i > 0 {
+                    write!(out, ", ")?;
+                }
+                write!(
+                    out,
+                    "{{\"color\": {}, \"quantity\": {}}}",
+                    quoted(self.name(inner)),
+                    quantity
+                )?;
+            }
+            let comma = if id + 1 < self.len() { "," } else { "" };
+            writeln!(out, "]{}", comma)?;
+        }
+        writeln!(out, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::tests::EXAMPLE;
+
+    fn dot(graph: &Graph) -> String {
+        let mut out = Vec::new();
+        graph.write_dot(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn json(graph: &Graph) -> String {
+        let mut out = Vec::new();
+        graph.write_json(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quoted("shiny gold"), r#""shiny gold""#);
+        assert_eq!(quoted(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quoted(r"back\slash"), r#""back\\slash""#);
+        assert_eq!(
+            quoted("two\nlines\tand a tab"),
+            r#""two\nlines\u0009and a tab""#
+        );
+        assert_eq!(quoted(""), r#""""#);
+    }
+
+    #[test]
+    fn example_dot() {
+        let graph: Graph = EXAMPLE.parse().unwrap();
+        assert_eq!(
+            dot(&graph),
+            r#"digraph bags {
+    node [shape=box, fontname=monospace];
+    n0 [label="light red"];
+    n1 [label="bright white"];
+    n2 [label="muted yellow"];
+    n3 [label="dark orange"];
+    n4 [label="shiny gold"];
+    n5 [label="faded blue"];
+    n6 [label="dark olive"];
+    n7 [label="vibrant plum"];
+    n8 [label="dotted black"];
+    n0 -> n1 [label="1"];
+    n0 -> n2 [label="2"];
+    n1 -> n4 [label="1"];
+    n2 -> n4 [label="2"];
+    n2 -> n5 [label="9"];
+    n3 -> n1 [label="3"];
+    n3 -> n2 [label="4"];
+    n4 -> n6 [label="1"];
+    n4 -> n7 [label="2"];
+    n6 -> n5 [label="3"];
+    n6 -> n8 [label="4"];
+    n7 -> n5 [label="5"];
+    n7 -> n8 [label="6"];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn example_json() {
+        let graph: Graph = EXAMPLE.parse().unwrap();
+        assert_eq!(
+            json(&graph),
+            r#"{
+  "light red": [{"color": "bright white", "quantity": 1}, {"color": "muted yellow", "quantity": 2}],
+  "bright white": [{"color": "shiny gold", "quantity": 1}],
+  "muted yellow": [{"color": "shiny gold", "quantity": 2}, {"color": "faded blue", "quantity": 9}],
+  "dark orange": [{"color": "bright white", "quantity": 3}, {"color": "muted yellow", "quantity": 4}],
+  "shiny gold": [{"color": "dark olive", "quantity": 1}, {"color": "vibrant plum", "quantity": 2}],
+  "faded blue": [],
+  "dark olive": [{"color": "faded blue", "quantity": 3}, {"color": "dotted black", "quantity": 4}],
+  "vibrant plum": [{"color": "faded blue", "quantity": 5}, {"color": "dotted black", "quantity": 6}],
+  "dotted black": []
+}
+"#
+        );
+    }
+
+    #[test]
+    fn example_paths() {
+        let graph: Graph = EXAMPLE.parse().unwrap();
+        let paths: Vec<String> = graph
+            .paths("light red", "faded blue")
+            .unwrap()
+            .iter()
+            .map(|path| path.to_string())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "light red -> bright white -> shiny gold -> dark olive -> faded blue: 3",
+                "light red -> bright white -> shiny gold -> vibrant plum -> faded blue: 10",
+                "light red -> muted yellow -> shiny gold -> dark olive -> faded blue: 12",
+                "light red -> muted yellow -> shiny gold -> vibrant plum -> faded blue: 40",
+                "light red -> muted yellow -> faded blue: 18",
+            ]
+        );
+    }
+
+    #[test]
+    fn odd_colors_are_escaped() {
+        let graph: Graph = "\"quoted\" red bags contain 2 back\\slash blue bags.\n"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            dot(&graph),
+            r#"digraph bags {
+    node [shape=box, fontname=monospace];
+    n0 [label="\"quoted\" red"];
+    n1 [label="back\\slash blue"];
+    n0 -> n1 [label="2"];
+}
+"#
+        );
+        assert_eq!(
+            json(&graph),
+            r#"{
+  "\"quoted\" red": [{"color": "back\\slash blue", "quantity": 2}],
+  "back\\slash blue": []
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,561 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::count::Count;
+use crate::error::{Error, ParseErrorKind};
+
+// The bag rules as a graph: one node per color, and an edge from each bag to
+// every color it must directly contain, with how many of them. `parents` is
+// the same edges the other way round, so containers are one search away.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Graph {
+    names: Vec<String>,
+    ids: HashMap<String, usize>,
+    edges: Vec<Vec<(usize, u32)>>,
+    parents: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    pub fn new() -> Graph {
+        Graph::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn colors(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(|name| name.as_str())
+    }
+
+    pub fn id(&self, color: &str) -> Option<usize> {
+        self.ids.get(color).copied()
+    }
+
+    pub fn name(&self, id: usize) -> &str {
+        &self.names[id]
+    }
+
+    // the ids and quantities a bag of color `id` holds directly, in rule order
+    pub fn children(&self, id: usize) -> &[(usize, u32)] {
+        &self.edges[id]
+    }
+
+    // the id of `color`, adding it if it is new
+    fn node(&mut self, color: &str) -> usize {
+        if let Some(&id) = self.ids.get(color) {
+            return id;
+        }
+        let id = self.names.len();
+        self.names.push(color.to_owned());
+        self.ids.insert(color.to_owned(), id);
+        self.edges.push(Vec::new());
+        self.parents.push(Vec::new());
+        id
+    }
+
+    fn lookup(&self, color: &str) -> Result<usize, Error> {
+        self.id(color)
+            .ok_or_else(|| Error::UnknownColor(color.to_owned()))
+    }
+
+    // `quantity` bags of `inner` go directly inside every `outer` bag
+    pub fn add_rule(&mut self, outer: &str, inner: &str, quantity: u32) {
+        let (outer, inner) = (self.node(outer), self.node(inner));
+        self.edges[outer].push((inner, quantity));
+        self.parents[inner].push(outer);
+    }
+
+    // colors whose rules mention `color` directly
+    pub fn direct_parents(&self, color: &str) -> Result<Vec<&str>, Error> {
+        let id = self.lookup(color)?;
+        Ok(self.parents[id]
+            .iter()
+            .map(|&outer| self.name(outer))
+            .collect())
+    }
+
+    // colors and quantities an `outer` bag holds directly, in rule order
+    pub fn direct_children(&self, color: &str) -> Result<Vec<(&str, u32)>, Error> {
+        let id = self.lookup(color)?;
+        Ok(self.edges[id]
+            .iter()
+            .map(|&(inner, quantity)| (self.name(inner), quantity))
+            .collect())
+    }
+
+    fn reachable(&self, source: usize) -> HashSet<usize> {
+        let mut stack = vec![source];
+        let mut visited = HashSet::new();
+        while let Some(node) = stack.pop() {
+            if visited.insert(node) {
+                stack.extend(self.edges[node].iter().map(|&(inner, _)| inner));
+            }
+        }
+        visited
+    }
+
+    // Can an `outer` bag eventually hold an `inner` one? A bag doesn't count as
+    // holding itself unless the rules go round in a circle. One search down
+    // from what `outer` holds, stopping as soon as it finds `inner`.
+    pub fn contains(&self, outer: &str, inner: &str) -> bool {
+        let (outer, inner) = match (self.id(outer), self.id(inner)) {
+            (Some(outer), Some(inner)) => (outer, inner),
+            _ => return false,
+        };
+        let mut seen = vec![false; self.len()];
+        let mut stack: Vec<usize> = self.edges[outer].iter().map(|&(child, _)| child).collect();
+        while let Some(node) = stack.pop() {
+            if node == inner {
+                return true;
+            }
+            if !seen[node] {
+                seen[node] = true;
+                stack.extend(self.edges[node].iter().map(|&(child, _)| child));
+            }
+        }
+        false
+    }
+
+    // Every color that can eventually hold a `color` bag, sorted: one
+    // breadth-first search up the parent edges.
+    pub fn containers_of(&self, color: &str) -> Result<Vec<&str>, Error> {
+        let target = self.lookup(color)?;
+        let mut seen = vec![false; self.len()];
+        let mut queue = VecDeque::new();
+        queue.push_back(target);
+        let mut containers = Vec::new();
+        while let Some(node) = queue.pop_front() {
+            for &outer in &self.parents[node] {
+                if !seen[outer] {
+                    seen[outer] = true;
+                    queue.push_back(outer);
+                    if outer != target {
+                        containers.push(self.name(outer));
+                    }
+                }
+            }
+        }
+        containers.sort_unstable();
+        Ok(containers)
+    }
+
+    // containers_of by a forward search from every color, O(V * (V + E));
+    // kept to check the fast one against
+    pub fn brute_force_containers_of(&self, color: &str) -> Result<Vec<&str>, Error> {
+        let target = self.lookup(color)?;
+        let mut containers: Vec<&str> = (0..self.len())
+            .filter(|&id| id != target && self.reachable(id).contains(&target))
+            .map(|id| self.name(id))
+            .collect();
+        containers.sort_unstable();
+        Ok(containers)
+    }
+
+    // The colors reachable from `roots`, each after everything it holds.
+    // Fails on the first cycle found on the way.
+    pub(crate) fn post_order(&self, roots: &[usize]) -> Result<Vec<usize>, Error> {
+        let mut order = Vec::new();
+        let mut done = vec![false; self.len()];
+        let mut on_path = vec![false; self.len()];
+        for &root in roots {
+            if done[root] {
+                continue;
+            }
+            // the colors being visited, each with the next edge to follow
+            let mut path = vec![(root, 0)];
+            on_path[root] = true;
+            while let Some(top) = path.last_mut() {
+                let (node, next) = *top;
+                if let Some(&(inner, _)) = self.edges[node].get(next) {
+                    top.1 += 1;
+                    if done[inner] {
+                        continue;
+                    }
+                    if on_path[inner] {
+                        let start = path.iter().position(|&(id, _)| id == inner).unwrap();
+                        let cycle = path[start..]
+                            .iter()
+                            .map(|&(id, _)| self.name(id).to_owned())
+                            .collect();
+                        return Err(Error::Cycle(cycle));
+                    }
+                    on_path[inner] = true;
+                    path.push((inner, 0));
+                    continue;
+                }
+                order.push(node);
+                done[node] = true;
+                on_path[node] = false;
+                path.pop();
+            }
+        }
+        Ok(order)
+    }
+
+    // How many bags each color reachable from `roots` holds, worked out once
+    // per color with children before parents. Fails on a cycle, or when a
+    // count doesn't fit in C.
+    fn counts<C: Count>(&self, roots: &[usize]) -> Result<Vec<Option<C>>, Error> {
+        let mut counts: Vec<Option<C>> = vec![None; self.len()];
+        for node in self.post_order(roots)? {
+            let mut total = C::zero();
+            for &(inner, quantity) in &self.edges[node] {
+                let inner = counts[inner].as_ref().unwrap();
+                total = total
+                    .add_bags(quantity, inner)
+                    .ok_or_else(|| Error::Overflow(self.name(node).to_owned()))?;
+            }
+            counts[node] = Some(total);
+        }
+        Ok(counts)
+    }
+
+    // how many bags a single `color` bag holds, all levels included, counted
+    // in C
+    pub fn count_contents<C: Count>(&self, color: &str) -> Result<C, Error> {
+        let id = self.lookup(color)?;
+        let mut counts = self.counts::<C>(&[id])?;
+        Ok(counts[id].take().unwrap())
+    }
+
+    // count_contents in a u64
+    pub fn total_contents(&self, color: &str) -> Result<u64, Error> {
+        self.count_contents(color)
+    }
+
+    // Every way a `from` bag holds `to` bags, in rule order, with how many
+    // `to` bags each one accounts for; together they add up to all the `to`
+    // bags inside. Only colors that can still lead to `to` are searched, and a
+    // cycle among those is an error since it would make the paths endless.
+    pub fn paths(&self, from: &str, to: &str) -> Result<Vec<Path<'_>>, Error> {
+        let (from, to) = (self.lookup(from)?, self.lookup(to)?);
+        let mut leads_to = vec![false; self.len()];
+        let mut queue = VecDeque::new();
+        leads_to[to] = true;
+        queue.push_back(to);
+        while let Some(node) = queue.pop_front() {
+            for &outer in &self.parents[node] {
+                if !leads_to[outer] {
+                    leads_to[outer] = true;
+                    queue.push_back(outer);
+                }
+            }
+        }
+
+        let mut paths = Vec::new();
+        let mut on_path = vec![false; self.len()];
+        // the colors so far, each with the bags of it per `from` bag and the
+        // next edge to follow
+        let mut path = vec![(from, 1u64, 0)];
+        on_path[from] = true;
+        while let Some(top) = path.last_mut() {
+            let (node, quantity, next) = *top;
+            let (inner, times) = match self.edges[node].get(next) {
+                Some(&edge) => edge,
+                None => {
+                    on_path[node] = false;
+                    path.pop();
+                    continue;
+                }
+            };
+            top.2 += 1;
+            if !leads_to[inner] {
+                continue;
+            }
+            let quantity = quantity
+                .checked_mul(u64::from(times))
+                .ok_or_else(|| Error::Overflow(self.name(inner).to_owned()))?;
+            if inner == to {
+                let mut colors: Vec<&str> = path.iter().map(|&(id, _, _)| self.name(id)).collect();
+                colors.push(self.name(to));
+                paths.push(Path { colors, quantity });
+            } else if on_path[inner] {
+                let start = path.iter().position(|&(id, _, _)| id == inner).unwrap();
+                let cycle = path[start..]
+                    .iter()
+                    .map(|&(id, _, _)| self.name(id).to_owned())
+                    .collect();
+                return Err(Error::Cycle(cycle));
+            } else {
+                on_path[inner] = true;
+                path.push((inner, quantity, 0));
+            }
+        }
+        Ok(paths)
+    }
+}
+
+// One chain of bags, outermost first, and how many of the last one it puts
+// inside a single bag of the first: the quantities along it multiplied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path<'a> {
+    pub colors: Vec<&'a str>,
+    pub quantity: u64,
+}
+
+impl fmt::Display for Path<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.colors.join(" -> "), self.quantity)
+    }
+}
+
+// "3 bright white bags" -> (3, "bright white")
+fn content(text: &str) -> Result<(u32, &str), ParseErrorKind> {
+    let bad = || ParseErrorKind::BadContent(text.to_owned());
+    let (quantity, rest) = text.split_once(' ').ok_or_else(bad)?;
+    let quantity = quantity
+        .parse()
+        .map_err(|_| ParseErrorKind::BadQuantity(quantity.to_owned()))?;
+    let color = rest
+        .strip_suffix(" bags")
+        .or_else(|| rest.strip_suffix(" bag"))
+        .ok_or_else(bad)?;
+    if color.trim().is_empty() {
+        return Err(bad());
+    }
+    Ok((quantity, color.trim()))
+}
+
+// a bag color and what it contains
+type Rule<'a> = (&'a str, Vec<(u32, &'a str)>);
+
+fn rule(line: &str) -> Result<Rule<'_>, ParseErrorKind> {
+    let syntax = || ParseErrorKind::Syntax(line.to_owned());
+    let body = line.trim().strip_suffix('.').ok_or_else(syntax)?;
+    let (outer, contents) = body.split_once(" bags contain ").ok_or_else(syntax)?;
+    let outer = outer.trim();
+    if outer.is_empty() {
+        return Err(syntax());
+    }
+    if contents.trim() == "no other bags" {
+        return Ok((outer, Vec::new()));
+    }
+    let contents = contents
+        .split(',')
+        .map(|item| content(item.trim()))
+        .collect::<Result<_, _>>()?;
+    Ok((outer, contents))
+}
+
+impl FromStr for Graph {
+    type Err = Error;
+
+    // One rule per line; blank lines are skipped and errors report the 1-based
+    // line. Colors that only ever appear inside other bags hold nothing.
+    fn from_str(s: &str) -> Result<Graph, Error> {
+        let mut graph = Graph::new();
+        let mut ruled = HashSet::new();
+        for (i, line) in s.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let error = |kind| Error::Parse { line: i + 1, kind };
+            let (outer, contents) = rule(line).map_err(error)?;
+            if !ruled.insert(outer.to_owned()) {
+                return Err(error(ParseErrorKind::DuplicateRule(outer.to_owned())));
+            }
+            graph.node(outer);
+            for (quantity, inner) in contents {
+                graph.add_rule(outer, inner, quantity);
+            }
+        }
+        Ok(graph)
+    }
+}
+
+impl TryFrom<&str> for Graph {
+    type Error = Error;
+
+    fn try_from(s: &str) -> Result<Graph, Error> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    pub(crate) const EXAMPLE: &str = "\
+light red bags contain 1 bright white bag, 2 muted yellow bags.
+dark orange bags contain 3 bright white bags, 4 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+faded blue bags contain no other bags.
+dotted black bags contain no other bags.
+";
+
+    const EXAMPLE2: &str = "\
+shiny gold bags contain 2 dark red bags.
+dark red bags contain 2 dark orange bags.
+dark orange bags contain 2 dark yellow bags.
+dark yellow bags contain 2 dark green bags.
+dark green bags contain 2 dark blue bags.
+dark blue bags contain 2 dark violet bags.
+dark violet bags contain no other bags.
+";
+
+    // how many `to` bags are inside a `from` bag, all levels included
+    fn bags_of(graph: &Graph, from: &str, to: &str) -> u64 {
+        graph
+            .direct_children(from)
+            .unwrap()
+            .into_iter()
+            .map(|(inner, quantity)| {
+                let own = if inner == to { 1 } else { 0 };
+                u64::from(quantity) * (own + bags_of(graph, inner, to))
+            })
+            .sum()
+    }
+
+    #[test]
+    fn puzzle_examples() {
+        let graph: Graph = EXAMPLE.parse().unwrap();
+        assert_eq!(
+            graph.containers_of("shiny gold"),
+            Ok(vec![
+                "bright white",
+                "dark orange",
+                "light red",
+                "muted yellow"
+            ])
+        );
+        assert_eq!(graph.total_contents("shiny gold"), Ok(32));
+        assert_eq!(graph.total_contents("vibrant plum"), Ok(11));
+        let graph = Graph::try_from(EXAMPLE2).unwrap();
+        assert_eq!(graph.total_contents("shiny gold"), Ok(126));
+    }
+
+    #[test]
+    fn containers_match_brute_force() {
+        let graph: Graph = EXAMPLE.parse().unwrap();
+        for color in graph.colors() {
+            assert_eq!(
+                graph.containers_of(color),
+                graph.brute_force_containers_of(color)
+            );
+        }
+        // random rules, circles included
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..50 {
+            let colors = rng.random_range(1..30);
+            let mut graph = Graph::new();
+            for i in 0..colors {
+                graph.node(&format!("shade{}", i));
+            }
+            for _ in 0..rng.random_range(0..colors * 2) {
+                let outer = format!("shade{}", rng.random_range(0..colors));
+                let inner = format!("shade{}", rng.random_range(0..colors));
+                graph.add_rule(&outer, &inner, 1);
+            }
+            for color in graph.colors() {
+                assert_eq!(
+                    graph.containers_of(color),
+                    graph.brute_force_containers_of(color)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn parsing() {
+        let graph: Graph = EXAMPLE.parse().unwrap();
+        assert_eq!(graph.len(), 9);
+        assert_eq!(
+            graph.direct_children("muted yellow"),
+            Ok(vec![("shiny gold", 2), ("faded blue", 9)])
+        );
+        assert_eq!(graph.direct_children("faded blue"), Ok(vec![]));
+        assert_eq!(
+            graph.direct_parents("shiny gold"),
+            Ok(vec!["bright white", "muted yellow"])
+        );
+        assert_eq!(
+            graph.direct_parents("plaid purple"),
+            Err(Error::UnknownColor("plaid purple".to_owned()))
+        );
+        // colors only seen inside other bags hold nothing
+        let graph: Graph = "\n  red bags contain 1 blue bag.  \n\n".parse().unwrap();
+        assert_eq!(graph.colors().collect::<Vec<_>>(), vec!["red", "blue"]);
+        assert_eq!(graph.direct_children("blue"), Ok(vec![]));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |rules: &str| rules.parse::<Graph>().map_err(|e| e.to_string());
+        assert_eq!(
+            error("red bags contain 1 blue bag.\nblue bags contain two red bags.\n"),
+            Err("line 2: bad quantity \"two\"".to_owned())
+        );
+        assert_eq!(
+            error("red bags contain 1 blue bag.\n\nred bags contain no other bags.\n"),
+            Err("line 3: second rule for \"red\"".to_owned())
+        );
+        assert_eq!(
+            error("red bags contain 1 blue bag\n"),
+            Err("line 1: expected `<color> bags contain <contents>.`, \
+                 found \"red bags contain 1 blue bag\""
+                .to_owned())
+        );
+        assert_eq!(
+            error("red bags contain 1 blue box.\n"),
+            Err("line 1: expected `<n> <color> bag(s)`, found \"1 blue box\"".to_owned())
+        );
+    }
+
+    #[test]
+    fn contains() {
+        let graph: Graph = EXAMPLE.parse().unwrap();
+        assert!(graph.contains("light red", "dotted black"));
+        assert!(graph.contains("bright white", "shiny gold"));
+        assert!(!graph.contains("shiny gold", "shiny gold"));
+        assert!(!graph.contains("shiny gold", "light red"));
+        assert!(!graph.contains("light red", "plaid purple"));
+        let cyclic: Graph = "a b bags contain 1 c d bag.\nc d bags contain 1 a b bag.\n"
+            .parse()
+            .unwrap();
+        assert!(cyclic.contains("a b", "a b"));
+    }
+
+    #[test]
+    fn paths() {
+        let graph: Graph = EXAMPLE.parse().unwrap();
+        assert_eq!(
+            graph.paths("light red", "shiny gold"),
+            Ok(vec![
+                Path {
+                    colors: vec!["light red", "bright white", "shiny gold"],
+                    quantity: 1
+                },
+                Path {
+                    colors: vec!["light red", "muted yellow", "shiny gold"],
+                    quantity: 4
+                },
+            ])
+        );
+        // the paths between two colors add up to every bag of one in the other
+        for from in graph.colors() {
+            for to in graph.colors() {
+                let through_paths: u64 = graph
+                    .paths(from, to)
+                    .unwrap()
+                    .iter()
+                    .map(|path| path.quantity)
+                    .sum();
+                assert_eq!(through_paths, bags_of(&graph, from, to));
+            }
+        }
+    }
+}
@@ -0,0 +1,10 @@
+pub mod count;
+pub mod error;
+pub mod export;
+pub mod graph;
+pub mod pack;
+
+pub use count::Count;
+pub use error::{Error, ParseErrorKind};
+pub use graph::{Graph, Path};
+pub use pack::{Goal, Packing, Weights};
@@ -62,113 +62,154 @@ In this example, a single shiny gold bag must contain 126 other bags.
 How many individual bags are required inside your single shiny gold bag?
 
 */
+use std::env;
+use std::fs;
 use std::io;
 use std::io::prelude::*;
-use regex::Regex;
-use std::collections::{HashMap, HashSet, LinkedList};
-
-#[derive(Debug)]
-struct Graph {
-    nodes_map: Vec<String>,
-    nodes: HashMap<String, usize>,
-    edges: HashMap<String, LinkedList<(usize, i32)>>
+use std::process;
+
+use num_bigint::BigUint;
+
+use day7::{Goal, Graph, Weights};
+
+// the rules on stdin, or the parse error and exit
+fn read_graph() -> io::Result<Graph> {
+    let mut input = String::new();
+    io::stdin().lock().read_to_string(&mut input)?;
+    Ok(input.parse().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    }))
 }
 
-impl Graph {
-    fn dfs(&self, source: usize) -> HashSet<usize> {
-        
-        let mut stack: LinkedList<usize> = LinkedList::new();
-        stack.push_front(source);
-
-        let mut visited = HashSet::new();
-        let mut reachable = HashSet::new();
-        while stack.len() > 0 {
-            let node = stack.pop_front().unwrap();
-            if visited.insert(node) {
-                reachable.insert(node);
-            }
-            let color = &self.nodes_map[node];
-            for (i, _) in self.edges.get(color).unwrap().iter() {
-                stack.push_front(*i);
-            }
-        }
-        return reachable;
+// day7 dot: the rules on stdin as a Graphviz digraph
+fn dot(args: &[String]) -> io::Result<()> {
+    if !args.is_empty() {
+        eprintln!("usage: day7 dot < rules");
+        process::exit(2);
     }
+    let stdout = io::stdout();
+    read_graph()?.write_dot(&mut stdout.lock())
+}
 
-    fn dfs_sum(&self, source: usize) -> i64 {
-        let mut sum = 1i64;
-
-        let color = &self.nodes_map[source];
-        let edge_list = self.edges.get(color).unwrap();
-        //println!("( {} | {:?}", source, edge_list);
-        for (i, q) in edge_list.iter() {
-            let val =  self.dfs_sum(*i);
-            let sum_l = i64::from(*q) * val;
-            //println!(" {}x{} = {}", q, val, sum_l);
-            sum += sum_l;
+// day7 json: the rules on stdin as JSON adjacency lists
+fn json(args: &[String]) -> io::Result<()> {
+    if !args.is_empty() {
+        eprintln!("usage: day7 json < rules");
+        process::exit(2);
+    }
+    let stdout = io::stdout();
+    read_graph()?.write_json(&mut stdout.lock())
+}
+
+// day7 paths <from> <to>: every chain of bags from one color down to another,
+// with how many `to` bags it adds to a `from` bag
+fn paths(args: &[String]) -> io::Result<()> {
+    if args.len() != 2 {
+        eprintln!("usage: day7 paths <from color> <to color> < rules");
+        process::exit(2);
+    }
+    let graph = read_graph()?;
+    let paths = graph.paths(&args[0], &args[1]).map_err(io::Error::other)?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for path in &paths {
+        writeln!(out, "{}", path)?;
+    }
+    Ok(())
+}
+
+// day7 pack <weights> <capacity> [heaviest|deepest] [color]: the best way to
+// fill one `color` bag, shiny gold by default, without going over `capacity`
+fn pack(args: &[String]) -> io::Result<()> {
+    let usage = || -> ! {
+        eprintln!("usage: day7 pack <weights> <capacity> [heaviest|deepest] [color] < rules");
+        process::exit(2);
+    };
+    if args.len() < 2 || args.len() > 4 {
+        usage();
+    }
+    let weights: Weights = fs::read_to_string(&args[0])?
+        .parse()
+        .map_err(io::Error::other)?;
+    let capacity: u64 = args[1].parse().unwrap_or_else(|_| usage());
+    let goal = match args.get(2).map(|a| a.as_str()) {
+        None | Some("heaviest") => Goal::Heaviest,
+        Some("deepest") => Goal::Deepest,
+        Some(_) => usage(),
+    };
+    let color = args.get(3).map_or("shiny gold", |a| a.as_str());
+    let graph = read_graph()?;
+    match graph
+        .pack(color, &weights, capacity, goal)
+        .map_err(io::Error::other)?
+    {
+        Some(packing) => {
+            print!("{}", packing);
+            println!(
+                "weight {}, {} bags, {} levels",
+                packing.weight,
+                packing.bags(),
+                packing.depth()
+            );
+            Ok(())
         }
-        //println!(" => {} )", sum);
-        return sum;
+        None => Err(io::Error::other(format!(
+            "an empty {} bag already weighs more than {}",
+            color, capacity
+        ))),
     }
 }
 
+// day7 [--bigint] [color]: how many colors can hold a `color` bag, shiny gold
+// by default, and how many bags one of them holds, counted in a u64 or with
+// --bigint in as many digits as it takes
 fn main() {
-    let stdin = io::stdin();
-    let rc_re = Regex::new(
-        r"((?P<quantity>\d+ )?(?P<color>\w+ \w+) bag[s]*)+"
-    ).unwrap();
-    let mut graph = Graph {
-        nodes: HashMap::new(),
-        nodes_map: Vec::new(),
-        edges: HashMap::new()
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match args.first().map(|a| a.as_str()) {
+        Some("dot") => Some(dot as fn(&[String]) -> io::Result<()>),
+        Some("json") => Some(json as fn(&[String]) -> io::Result<()>),
+        Some("paths") => Some(paths as fn(&[String]) -> io::Result<()>),
+        Some("pack") => Some(pack as fn(&[String]) -> io::Result<()>),
+        _ => None,
     };
-    let mut i = 0;
-    for line in stdin.lock().lines() {
-        let sline = line.unwrap().to_owned();
-        //println!("{}", sline);
-        let mut first = true;
-        let mut root = 0;
-        for cap in rc_re.captures_iter(&sline) {
-            let color = cap["color"].trim().to_owned();
-            if color != "no other" {
-                if ! graph.nodes.contains_key(&color) {
-                    //print!(".");
-                    graph.nodes_map.push(color.clone());
-                    graph.nodes.insert(color.clone(), i);
-                    i += 1;
-                }   
-                if first {
-                    root = *graph.nodes.get(&color).unwrap();
-                    first = false;
-                    //println!("\t({})", color);
-                    graph.edges.insert(color.clone(), LinkedList::new());
-                } else {
-                    let root_str = &graph.nodes_map[root];
-                    let color_i = *graph.nodes.get(&color).unwrap();
-                    let quantity = cap["quantity"].trim().parse::<i32>().unwrap_or(-1);
-                    //println!("\t\t[{} {}]->[{} {}]", root_str, root, color, color_i);
-                    graph.edges.get_mut(root_str).unwrap().push_back((color_i, quantity));
-                }
-            }
+    if let Some(command) = command {
+        if let Err(e) = command(&args[1..]) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let mut color = "shiny gold".to_owned();
+    let mut bigint = false;
+    for arg in args {
+        match arg.as_str() {
+            "--bigint" => bigint = true,
+            _ => color = arg,
         }
     }
-    assert_eq!(graph.nodes.len(), graph.nodes_map.len());
-    //println!("{:?}", graph);
-    let target = *graph.nodes.get("shiny gold").unwrap();
-    //println!("{}", target);
-    
-    let mut can_reach = Vec::new();
-    for i in 0..graph.nodes.len() {
-        if i != target { // dont run the dfs from the target as it will yeild wrong results
-            let reachable = graph.dfs(i);
-            if reachable.contains(&target) {
-                //println!("{} {:?}", i, reachable);
-                can_reach.push(i);
-            }
+    let mut input = String::new();
+    io::stdin().lock().read_to_string(&mut input).unwrap();
+    let graph: Graph = input.parse().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let total = if bigint {
+        graph
+            .count_contents::<BigUint>(&color)
+            .map(|n| n.to_string())
+    } else {
+        graph.total_contents(&color).map(|n| n.to_string())
+    };
+    match (graph.containers_of(&color), total) {
+        (Ok(containers), Ok(total)) => {
+            println!("{}", containers.len());
+            println!("{}", total);
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            process::exit(1);
         }
     }
-    //println!("{:?}", can_reach);
-    println!("{}", can_reach.len());
-
-    println!("{}", graph.dfs_sum(target) - 1);
 }
@@ -0,0 +1,625 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{Error, ParseErrorKind};
+use crate::graph::Graph;
+
+// What a bag of each color weighs, or how much room it takes up: one color
+// per line as `<color>: <weight>`, blank lines skipped.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Weights(HashMap<String, u64>);
+
+impl Weights {
+    pub fn new() -> Weights {
+        Weights::default()
+    }
+
+    pub fn set(&mut self, color: &str, weight: u64) {
+        self.0.insert(color.to_owned(), weight);
+    }
+
+    pub fn get(&self, color: &str) -> Option<u64> {
+        self.0.get(color).copied()
+    }
+}
+
+impl FromStr for Weights {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Weights, Error> {
+        let mut weights = Weights::new();
+        for (i, line) in s.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let error = |kind| Error::Parse { line: i + 1, kind };
+            let syntax = || error(ParseErrorKind::WeightSyntax(line.to_owned()));
+            let (color, weight) = line.split_once(':').ok_or_else(syntax)?;
+            let (color, weight) = (color.trim(), weight.trim());
+            if color.is_empty() {
+                return Err(syntax());
+            }
+            let weight = weight
+                .parse()
+                .map_err(|_| error(ParseErrorKind::BadWeight(weight.to_owned())))?;
+            if weights.get(color).is_some() {
+                return Err(error(ParseErrorKind::DuplicateWeight(color.to_owned())));
+            }
+            weights.set(color, weight);
+        }
+        Ok(weights)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Goal {
+    // as much weight as fits
+    Heaviest,
+    // as many levels of bags inside bags as fit, as light as can be
+    Deepest,
+}
+
+// A bag and what was packed into it, in rule order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packing<'a> {
+    pub color: &'a str,
+    // the bag itself and everything inside it
+    pub weight: u64,
+    pub contents: Vec<Packing<'a>>,
+}
+
+impl Packing<'_> {
+    // levels of bags, counting this one
+    pub fn depth(&self) -> usize {
+        1 + self.contents.iter().map(|p| p.depth()).max().unwrap_or(0)
+    }
+
+    // bags, counting this one
+    pub fn bags(&self) -> usize {
+        1 + self.contents.iter().map(|p| p.bags()).sum::<usize>()
+    }
+
+    fn write_tree(&self, f: &mut fmt::Formatter, copies: usize, indent: usize) -> fmt::Result {
+        write!(f, "{:indent$}", "", indent = indent)?;
+        if copies > 1 {
+            write!(f, "{} x ", copies)?;
+        }
+        writeln!(f, "{} ({})", self.color, self.weight)?;
+        let mut i = 0;
+        while i < self.contents.len() {
+            let first = &self.contents[i];
+            let run = self.contents[i..]
+                .iter()
+                .take_while(|p| *p == first)
+                .count();
+            first.write_tree(f, run, indent + 2)?;
+            i += run;
+        }
+        Ok(())
+    }
+}
+
+// One line per bag, indented under the bag holding it; identical bags side by
+// side are written once as `n x ...`. Weights include the contents.
+impl fmt::Display for Packing<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_tree(f, 1, 0)
+    }
+}
+
+// The most bits the tables of a heaviest packing may take up, 512 MiB
+const MAX_BITS: u64 = 1 << 32;
+
+// A set of weights from 0 to len - 1, one bit each
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Bits {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Bits {
+    fn new(len: usize) -> Bits {
+        Bits {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        }
+    }
+
+    fn has(&self, i: usize) -> bool {
+        i < self.len && self.words[i / 64] >> (i % 64) & 1 == 1
+    }
+
+    fn set(&mut self, i: usize) {
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    fn max(&self) -> Option<usize> {
+        (0..self.len).rev().find(|&i| self.has(i))
+    }
+
+    fn ones(&self) -> u32 {
+        self.words.iter().map(|word| word.count_ones()).sum()
+    }
+
+    // every a + b below `len`, for a in self and b in other
+    fn sums(&self, other: &Bits, len: usize) -> Bits {
+        // the set with more weights in it is shifted by each of the other's
+        let (few, many) = if self.ones() <= other.ones() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let mut out = Bits::new(len);
+        for shift in (0..cmp::min(few.len, len)).filter(|&b| few.has(b)) {
+            let (words, bits) = (shift / 64, shift % 64);
+            let end = cmp::min(out.words.len(), words + many.words.len() + 1);
+            for i in words..end {
+                let j = i - words;
+                let mut word = many.words.get(j).map_or(0, |w| w << bits);
+                if bits > 0 && j > 0 {
+                    word |= many.words[j - 1] >> (64 - bits);
+                }
+                out.words[i] |= word;
+            }
+        }
+        if !out.len.is_multiple_of(64) {
+            *out.words.last_mut().unwrap() &= (1 << (out.len % 64)) - 1;
+        }
+        out
+    }
+}
+
+// How a heaviest packing was put together, for taking the bags back out.
+// `powers[c][j]` holds the weights 2^j bags of color `c` can come to between
+// them, each packed or left out, and `steps[c]` goes through the bags that
+// went into a `c` bag: a run of 2^j bags of one color and the weights before.
+struct Tables {
+    powers: Vec<Vec<Bits>>,
+    steps: Vec<Vec<(usize, usize, Bits)>>,
+}
+
+impl Graph {
+    // The best way to fill one `color` bag without its total weight going over
+    // `capacity`. The rules work as a bill of materials: a bag may hold up to
+    // the listed quantity of each color, each of those packed the same way,
+    // and anything can be left out. None when an empty `color` bag is already
+    // too heavy.
+    //
+    // Heaviest is a subset sum over every weight up to `capacity`, or up to
+    // what a full bag weighs if that is less, so that is what the work and
+    // memory grow with; a capacity that would take more than MAX_BITS of
+    // tables is an error. Deepest only follows one bag per level and doesn't
+    // depend on it.
+    pub fn pack(
+        &self,
+        color: &str,
+        weights: &Weights,
+        capacity: u64,
+        goal: Goal,
+    ) -> Result<Option<Packing<'_>>, Error> {
+        let root = self
+            .id(color)
+            .ok_or_else(|| Error::UnknownColor(color.to_owned()))?;
+        let order = self.post_order(&[root])?;
+        let mut weight = vec![0; self.len()];
+        for &node in &order {
+            let name = self.name(node);
+            weight[node] = weights
+                .get(name)
+                .ok_or_else(|| Error::MissingWeight(name.to_owned()))?;
+        }
+        match goal {
+            Goal::Heaviest => self.heaviest(root, &order, &weight, capacity),
+            Goal::Deepest => Ok(self.deepest(root, &order, &weight, capacity)),
+        }
+    }
+
+    fn heaviest(
+        &self,
+        root: usize,
+        order: &[usize],
+        weight: &[u64],
+        capacity: u64,
+    ) -> Result<Option<Packing<'_>>, Error> {
+        // what a bag of each color weighs packed full, None past u64::MAX
+        let mut full: Vec<Option<u64>> = vec![None; self.len()];
+        for &node in order {
+            let total =
+                self.children(node)
+                    .iter()
+                    .try_fold(weight[node], |total, &(inner, quantity)| {
+                        full[inner]?
+                            .checked_mul(u64::from(quantity))?
+                            .checked_add(total)
+                    });
+            full[node] = total;
+        }
+        let capacity = full[root].map_or(capacity, |full| cmp::min(full, capacity));
+        // bitsets in the tables: one per color, and one step and at most one
+        // power per bit of each quantity, none longer than capacity + 1
+        let bitsets = self
+            .quantities(order)
+            .try_fold(order.len() as u64, |bits, quantity| {
+                bits.checked_add(2 * u64::from(32 - quantity.leading_zeros()))
+            });
+        let too_large = || Error::TooLarge(self.name(root).to_owned());
+        match bitsets
+            .zip(capacity.checked_add(1))
+            .and_then(|(bitsets, len)| bitsets.checked_mul(len))
+        {
+            Some(bits) if bits <= MAX_BITS => {}
+            _ => return Err(too_large()),
+        }
+        let capacity = usize::try_from(capacity).map_err(|_| too_large())?;
+        // the weights a packed bag of each color can come to
+        let mut reach: Vec<Option<Bits>> = vec![None; self.len()];
+        let mut tables = Tables {
+            powers: vec![Vec::new(); self.len()],
+            steps: vec![Vec::new(); self.len()],
+        };
+        for &node in order {
+            let top = full[node].map_or(capacity, |full| cmp::min(full, capacity as u64) as usize);
+            let mut layer = Bits::new(top + 1);
+            if weight[node] <= top as u64 {
+                layer.set(weight[node] as usize);
+            }
+            for &(inner, quantity) in self.children(node) {
+                // a quantity of n is taken as runs of 1, 2, 4, ... bags, one
+                // for each bit of n
+                for j in (0..32).filter(|j| quantity >> j & 1 == 1) {
+                    let run = tables.power(&reach, inner, j, capacity);
+                    let next = layer.sums(run, top + 1);
+                    // more of the same can't reach anything new either
+                    if next == layer {
+                        break;
+                    }
+                    let before = std::mem::replace(&mut layer, next);
+                    tables.steps[node].push((inner, j, before));
+                }
+            }
+            reach[node] = Some(layer);
+        }
+        let best = match reach[root].as_ref().unwrap().max() {
+            Some(best) => best,
+            None => return Ok(None),
+        };
+        Ok(Some(self.unpack(&tables, root, best)))
+    }
+
+    // the quantities in the rules for the colors in `order`
+    fn quantities<'a>(&'a self, order: &'a [usize]) -> impl Iterator<Item = u32> + 'a {
+        order
+            .iter()
+            .flat_map(move |&node| self.children(node).iter().map(|&(_, quantity)| quantity))
+    }
+
+    // a `node` bag that comes to `target`, taking its bags back out
+    fn unpack(&self, tables: &Tables, node: usize, target: usize) -> Packing<'_> {
+        let mut left = target;
+        let mut contents = Vec::new();
+        for (inner, j, before) in tables.steps[node].iter().rev() {
+            if before.has(left) {
+                continue;
+            }
+            let run = &tables.powers[*inner][*j];
+            let packed = (1..=left)
+                .find(|&w| run.has(w) && before.has(left - w))
+                .unwrap();
+            self.unpack_run(tables, *inner, *j, packed, &mut contents);
+            left -= packed;
+        }
+        contents.reverse();
+        Packing {
+            color: self.name(node),
+            weight: target as u64,
+            contents,
+        }
+    }
+
+    // 2^j bags of color `node` that come to `target` between them, halving
+    // the run until it is down to single bags; empty places are left out
+    fn unpack_run<'a>(
+        &'a self,
+        tables: &Tables,
+        node: usize,
+        j: usize,
+        target: usize,
+        out: &mut Vec<Packing<'a>>,
+    ) {
+        if target == 0 {
+            return;
+        }
+        if j == 0 {
+            out.push(self.unpack(tables, node, target));
+            return;
+        }
+        let half = &tables.powers[node][j - 1];
+        let first = (0..=target)
+            .find(|&w| half.has(w) && half.has(target - w))
+            .unwrap();
+        self.unpack_run(tables, node, j - 1, first, out);
+        self.unpack_run(tables, node, j - 1, target - first, out);
+    }
+
+    fn deepest(
+        &self,
+        root: usize,
+        order: &[usize],
+        weight: &[u64],
+        capacity: u64,
+    ) -> Option<Packing<'_>> {
+        // lightest[node][k]: the lightest chain of k + 1 bags from a `node`,
+        // as long as it is within `capacity`, so with no weightless bags
+        // none of these is longer than capacity + 1
+        let mut lightest: Vec<Vec<u64>> = vec![Vec::new(); self.len()];
+        for &node in order {
+            if weight[node] > capacity {
+                continue;
+            }
+            let mut chains = vec![weight[node]];
+            for &(inner, quantity) in self.children(node) {
+                if quantity == 0 {
+                    continue;
+                }
+                // chains only get heavier the longer they are, so once one
+                // goes over capacity the rest can't fit either
+                for (k, &chain) in lightest[inner].iter().enumerate() {
+                    let chain = match weight[node].checked_add(chain) {
+                        Some(chain) if chain <= capacity => chain,
+                        _ => break,
+                    };
+                    match chains.get_mut(k + 1) {
+                        Some(best) => *best = cmp::min(*best, chain),
+                        None => chains.push(chain),
+                    }
+                }
+            }
+            lightest[node] = chains;
+        }
+        let mut k = lightest[root].iter().rposition(|&w| w <= capacity)?;
+        let mut chain = vec![root];
+        while k > 0 {
+            let node = *chain.last().unwrap();
+            let rest = lightest[node][k] - weight[node];
+            let next = self
+                .children(node)
+                .iter()
+                .find(|&&(inner, quantity)| {
+                    quantity > 0 && lightest[inner].get(k - 1) == Some(&rest)
+                })
+                .unwrap()
+                .0;
+            chain.push(next);
+            k -= 1;
+        }
+        let mut packing: Option<Packing> = None;
+        for (level, &node) in chain.iter().enumerate().rev() {
+            let depth = chain.len() - 1 - level;
+            packing = Some(Packing {
+                color: self.name(node),
+                weight: lightest[node][depth],
+                contents: packing.into_iter().collect(),
+            });
+        }
+        packing
+    }
+}
+
+impl Tables {
+    // 2^j bags of `inner`, worked out from the one before the first time
+    // they are asked for
+    fn power(&mut self, reach: &[Option<Bits>], inner: usize, j: usize, capacity: usize) -> &Bits {
+        let powers = &mut self.powers[inner];
+        if powers.is_empty() {
+            // a bag of `inner` or nothing
+            let mut one = reach[inner].clone().unwrap();
+            one.set(0);
+            powers.push(one);
+        }
+        while powers.len() <= j {
+            let last = powers.last().unwrap();
+            let len = cmp::min(2 * (last.len - 1), capacity) + 1;
+            let next = last.sums(last, len);
+            powers.push(next);
+        }
+        &powers[j]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(rules: &str) -> Graph {
+        rules.parse().unwrap()
+    }
+
+    // every bag weighs what it says: itself plus what is inside it
+    fn adds_up(packing: &Packing, weights: &Weights) -> bool {
+        let inside: u64 = packing.contents.iter().map(|p| p.weight).sum();
+        packing.weight == weights.get(packing.color).unwrap() + inside
+            && packing.contents.iter().all(|p| adds_up(p, weights))
+    }
+
+    #[test]
+    fn puzzle_example() {
+        let graph = graph(crate::graph::tests::EXAMPLE);
+        // with every bag weighing 1, a full shiny gold bag weighs 1 + 32
+        let mut weights = Weights::new();
+        for color in graph.colors() {
+            weights.set(color, 1);
+        }
+        let heaviest = graph
+            .pack("shiny gold", &weights, 100, Goal::Heaviest)
+            .unwrap()
+            .unwrap();
+        assert_eq!((heaviest.weight, heaviest.bags()), (33, 33));
+        let heaviest = graph
+            .pack("shiny gold", &weights, 10, Goal::Heaviest)
+            .unwrap()
+            .unwrap();
+        assert_eq!((heaviest.weight, heaviest.bags()), (10, 10));
+        let deepest = graph
+            .pack("light red", &weights, 100, Goal::Deepest)
+            .unwrap()
+            .unwrap();
+        assert_eq!((deepest.weight, deepest.depth()), (5, 5));
+        assert_eq!(
+            graph.pack("light red", &weights, 0, Goal::Deepest),
+            Ok(None)
+        );
+        // a plum bag of 30 doesn't fit a shiny gold bag of 40, two olive bags do
+        let weights: Weights = "\
+shiny gold: 10
+dark olive: 5
+vibrant plum: 30
+faded blue: 1
+dotted black: 2
+"
+        .parse()
+        .unwrap();
+        let heaviest = graph
+            .pack("shiny gold", &weights, 40, Goal::Heaviest)
+            .unwrap()
+            .unwrap();
+        assert_eq!(heaviest.weight, 40);
+        assert!(adds_up(&heaviest, &weights));
+        let deepest = graph
+            .pack("shiny gold", &weights, 40, Goal::Deepest)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            deepest.to_string(),
+            "shiny gold (16)\n  dark olive (6)\n    faded blue (1)\n"
+        );
+        assert_eq!(
+            graph.pack("light red", &weights, 40, Goal::Deepest),
+            Err(Error::MissingWeight("bright white".to_owned()))
+        );
+    }
+
+    #[test]
+    fn weight_errors() {
+        let error = |text: &str| text.parse::<Weights>().map_err(|e| e.to_string());
+        assert_eq!(
+            error("shiny gold: ten\n"),
+            Err("line 1: bad weight \"ten\"".to_owned())
+        );
+        assert_eq!(
+            error("shiny gold: 1\n\nshiny gold: 2\n"),
+            Err("line 3: second weight for \"shiny gold\"".to_owned())
+        );
+        assert_eq!(
+            error("shiny gold 1\n"),
+            Err("line 1: expected `<color>: <weight>`, found \"shiny gold 1\"".to_owned())
+        );
+    }
+
+    #[test]
+    fn large_quantities() {
+        let graph = graph("shiny gold bags contain 1000 faded blue bags.\nfaded blue bags contain no other bags.\n");
+        let weights: Weights = "shiny gold: 1\nfaded blue: 3\n".parse().unwrap();
+        let packing = graph
+            .pack("shiny gold", &weights, 2000, Goal::Heaviest)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            packing.to_string(),
+            "shiny gold (1999)\n  666 x faded blue (3)\n"
+        );
+        let full = graph
+            .pack("shiny gold", &weights, u64::MAX, Goal::Heaviest)
+            .unwrap()
+            .unwrap();
+        assert_eq!((full.weight, full.bags()), (3001, 1001));
+    }
+
+    #[test]
+    fn nested_runs_add_up() {
+        let graph = graph(
+            "shiny gold bags contain 5 dark olive bags.\n\
+             dark olive bags contain 3 faded blue bags.\n\
+             faded blue bags contain no other bags.\n",
+        );
+        let weights: Weights = "shiny gold: 1\ndark olive: 2\nfaded blue: 4\n"
+            .parse()
+            .unwrap();
+        for capacity in 0..=80 {
+            let packing = graph
+                .pack("shiny gold", &weights, capacity, Goal::Heaviest)
+                .unwrap();
+            let packing = match packing {
+                Some(packing) => packing,
+                None => {
+                    assert_eq!(capacity, 0);
+                    continue;
+                }
+            };
+            assert!(adds_up(&packing, &weights), "{}", packing);
+            // n olive bags hold up to 3n blue ones between them
+            let best = (0..=5u64)
+                .flat_map(|olive| (0..=3 * olive).map(move |blue| 1 + 2 * olive + 4 * blue))
+                .filter(|&weight| weight <= capacity)
+                .max();
+            assert_eq!(Some(packing.weight), best, "{}", packing);
+        }
+    }
+
+    #[test]
+    fn capacities_past_memory() {
+        let graph = graph(
+            "shiny gold bags contain 2 faded blue bags.\nfaded blue bags contain no other bags.\n",
+        );
+        let weights: Weights = format!("shiny gold: 1\nfaded blue: {}\n", u64::MAX)
+            .parse()
+            .unwrap();
+        assert_eq!(
+            graph.pack("shiny gold", &weights, u64::MAX, Goal::Heaviest),
+            Err(Error::TooLarge("shiny gold".to_owned()))
+        );
+        // a chain whose weight doesn't fit a u64 doesn't fit the bag either
+        let deepest = graph
+            .pack("shiny gold", &weights, u64::MAX, Goal::Deepest)
+            .unwrap()
+            .unwrap();
+        assert_eq!((deepest.weight, deepest.depth()), (1, 1));
+        let weights: Weights = "shiny gold: 1\nfaded blue: 1\n".parse().unwrap();
+        let deepest = graph
+            .pack("shiny gold", &weights, u64::MAX, Goal::Deepest)
+            .unwrap()
+            .unwrap();
+        assert_eq!((deepest.weight, deepest.depth()), (2, 2));
+    }
+
+    #[test]
+    fn long_chains_small_capacity() {
+        let n = 30_000;
+        let mut rules = String::new();
+        let mut weights = String::new();
+        for i in 0..n {
+            rules.push_str(&format!(
+                "pale c{} bags contain 1 pale c{} bag.\n",
+                i,
+                i + 1
+            ));
+            weights.push_str(&format!("pale c{}: 1\n", i));
+        }
+        rules.push_str(&format!("pale c{} bags contain no other bags.\n", n));
+        weights.push_str(&format!("pale c{}: 1\n", n));
+        let graph = graph(&rules);
+        let weights: Weights = weights.parse().unwrap();
+        let deepest = graph
+            .pack("pale c0", &weights, 10, Goal::Deepest)
+            .unwrap()
+            .unwrap();
+        assert_eq!((deepest.weight, deepest.depth()), (10, 10));
+        let deepest = graph
+            .pack(&format!("pale c{}", n - 2), &weights, 10, Goal::Deepest)
+            .unwrap()
+            .unwrap();
+        assert_eq!((deepest.weight, deepest.depth()), (3, 3));
+    }
+}
@@ -0,0 +1,88 @@
+use std::env;
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use day8::{brute_force_repairs, repairs, Instruction, Program};
+
+// Lays the program out as blocks of acc/nop ending in a jmp to the next block in a
+// shuffled order, so a run visits every instruction once. The last block ends in a
+// corrupted `nop` that should have been the `jmp` to the exit.
+fn generate(len: usize, seed: u64) -> Program {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut starts = Vec::new();
+    let mut pc = 0;
+    while pc < len {
+        starts.push(pc);
+        pc += rng.random_range(2..10);
+    }
+    starts.push(len);
+    let blocks = starts.len() - 1;
+    let mut order: Vec<usize> = (1..blocks).collect();
+    order.shuffle(&mut rng);
+    order.insert(0, 0);
+    // the corrupted nop must not fall through to the exit by accident
+    if order[blocks - 1] == blocks - 1 && blocks > 2 {
+        order.swap(blocks - 1, blocks - 2);
+    }
+
+    let mut instructions = Vec::with_capacity(len);
+    for _ in 0..len {
+        let arg = rng.random_range(-100..100);
+        if rng.random_ratio(1, 4) {
+            instructions.push(Instruction::Nop(arg));
+        } else {
+            instructions.push(Instruction::Acc(arg));
+        }
+    }
+    for (k, &block) in order.iter().enumerate() {
+        let last = starts[block + 1] - 1;
+        let target = if k + 1 < blocks {
+            starts[order[k + 1]]
+        } else {
+            len
+        };
+        let offset = target as i32 - last as i32;
+        instructions[last] = if k + 1 < blocks {
+            Instruction::Jmp(offset)
+        } else {
+            Instruction::Nop(offset)
+        };
+    }
+    Program::new(instructions)
+}
+
+fn main() {
+    let sizes: Vec<usize> = env::args()
+        .skip(1)
+        .filter_map(|arg| arg.parse().ok())
+        .collect();
+    let sizes = if sizes.is_empty() {
+        vec![1_000, 10_000, 100_000]
+    } else {
+        sizes
+    };
+    for len in sizes {
+        let program = generate(len, 0x2020_0008 ^ len as u64);
+
+        let start = Instant::now();
+        let fast = repairs(&program);
+        let fast_time = start.elapsed();
+
+        let start = Instant::now();
+        let slow = brute_force_repairs(&program);
+        let slow_time = start.elapsed();
+
+        assert_eq!(fast, slow);
+        assert!(!fast.is_empty());
+        println!(
+            "{:>8} instructions: {} repair(s), linear {:?}, brute force {:?}",
+            program.len(),
+            fast.len(),
+            fast_time,
+            slow_time
+        );
+    }
+}
@@ -0,0 +1,423 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::instruction::Instruction;
+use crate::program::Program;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Block(usize),
+    Terminates,
+    OutOfBounds(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    pub end: usize,
+    pub exit: Exit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    AlwaysTerminates,
+    AlwaysLoops,
+    LeavesBounds,
+    Unreachable,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Verdict::AlwaysTerminates => write!(f, "always terminates"),
+            Verdict::AlwaysLoops => write!(f, "always loops"),
+            Verdict::LeavesBounds => write!(f, "jumps out of bounds"),
+            Verdict::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub blocks: Vec<Block>,
+    // strongly connected components of the block graph that contain a cycle
+    pub loops: Vec<Vec<usize>>,
+    // (pc, target) of every jmp that lands neither inside the program nor on its end
+    pub out_of_bounds: Vec<(usize, i64)>,
+    pub verdicts: Vec<Verdict>,
+    instructions: Vec<Instruction>,
+}
+
+// Tarjan's algorithm without recursion, so deep chains of blocks can't blow the
+// stack. Components come out in reverse topological order: every component is
+// emitted after the ones it can reach.
+fn tarjan(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let n = edges.len();
+    let mut index = vec![usize::MAX; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = Vec::new();
+    let mut components = Vec::new();
+    let mut next_index = 0;
+
+    for root in 0..n {
+        if index[root] != usize::MAX {
+            continue;
+        }
+        let mut work = vec![(root, 0)];
+        while let Some(&mut (node, ref mut edge)) = work.last_mut() {
+            if *edge == 0 {
+                index[node] = next_index;
+                low[node] = next_index;
+                next_index += 1;
+                stack.push(node);
+                on_stack[node] = true;
+            }
+            if let Some(&next) = edges[node].get(*edge) {
+                *edge += 1;
+                if index[next] == usize::MAX {
+                    work.push((next, 0));
+                } else if on_stack[next] {
+                    low[node] = low[node].min(index[next]);
+                }
+                continue;
+            }
+            work.pop();
+            if let Some(&(parent, _)) = work.last() {
+                low[parent] = low[parent].min(low[node]);
+            }
+            if low[node] == index[node] {
+                let mut component = Vec::new();
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                component.reverse();
+                components.push(component);
+            }
+        }
+    }
+    components
+}
+
+impl Analysis {
+    pub fn analyze(program: &Program) -> Analysis {
+        let len = program.len();
+        let instructions = program.instructions().to_vec();
+
+        let mut leader = vec![false; len];
+        let mut out_of_bounds = Vec::new();
+        if len > 0 {
+            leader[0] = true;
+        }
+        for (pc, instruction) in instructions.iter().enumerate() {
+            if let Instruction::Jmp(_) = instruction {
+                let target = instruction.next_pc(pc as i64);
+                if target >= 0 && target < len as i64 {
+                    leader[target as usize] = true;
+                } else if target != len as i64 {
+                    out_of_bounds.push((pc, target));
+                }
+                if pc + 1 < len {
+                    leader[pc + 1] = true;
+                }
+            }
+        }
+
+        let mut block_of = vec![0; len];
+        let mut starts = Vec::new();
+        for pc in 0..len {
+            if leader[pc] {
+                starts.push(pc);
+            }
+            block_of[pc] = starts.len() - 1;
+        }
+        let blocks: Vec<Block> = starts
+            .iter()
+            .enumerate()
+            .map(|(b, &start)| {
+                let end = starts.get(b + 1).copied().unwrap_or(len);
+                let target = instructions[end - 1].next_pc(end as i64 - 1);
+                let exit = if target == len as i64 {
+                    Exit::Terminates
+                } else if target < 0 || target > len as i64 {
+                    Exit::OutOfBounds(target)
+                } else {
+                    Exit::Block(block_of[target as usize])
+                };
+                Block { start, end, exit }
+            })
+            .collect();
+
+        let edges: Vec<Vec<usize>> = blocks
+            .iter()
+            .map(|block| match block.exit {
+                Exit::Block(next) => vec![next],
+                _ => Vec::new(),
+            })
+            .collect();
+
+        let mut block_verdicts = vec![Verdict::Unreachable; blocks.len()];
+        let mut loops = Vec::new();
+        for component in tarjan(&edges) {
+            let first = component[0];
+            if component.len() > 1 || edges[first].contains(&first) {
+                for &b in component.iter() {
+                    block_verdicts[b] = Verdict::AlwaysLoops;
+                }
+                loops.push(component);
+            } else {
+                block_verdicts[first] = match blocks[first].exit {
+                    Exit::Terminates => Verdict::AlwaysTerminates,
+                    Exit::OutOfBounds(_) => Verdict::LeavesBounds,
+                    Exit::Block(next) => block_verdicts[next],
+                };
+            }
+        }
+        loops.sort();
+
+        let mut reachable = vec![false; blocks.len()];
+        let mut queue = VecDeque::new();
+        if !blocks.is_empty() {
+            reachable[0] = true;
+            queue.push_back(0);
+        }
+        while let Some(b) = queue.pop_front() {
+            for &next in edges[b].iter() {
+                if !reachable[next] {
+                    reachable[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        let verdicts = (0..len)
+            .map(|pc| {
+                let b = block_of[pc];
+                if reachable[b] {
+                    block_verdicts[b]
+                } else {
+                    Verdict::Unreachable
+                }
+            })
+            .collect();
+
+        Analysis {
+            blocks,
+            loops,
+            out_of_bounds,
+            verdicts,
+            instructions,
+        }
+    }
+
+    // what running the whole program does; an empty program terminates at once
+    pub fn program_verdict(&self) -> Verdict {
+        self.verdicts
+            .first()
+            .copied()
+            .unwrap_or(Verdict::AlwaysTerminates)
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "program {}", self.program_verdict())?;
+        writeln!(f, "blocks:")?;
+        for (b, block) in self.blocks.iter().enumerate() {
+            let exit = match block.exit {
+                Exit::Block(next) => format!("b{}", next),
+                Exit::Terminates => "end".to_owned(),
+                Exit::OutOfBounds(target) => format!("out of bounds ({})", target),
+            };
+            writeln!(f, "  b{} {}..{} -> {}", b, block.start, block.end, exit)?;
+        }
+        writeln!(f, "loops:")?;
+        for component in self.loops.iter() {
+            let names: Vec<String> = component.iter().map(|b| format!("b{}", b)).collect();
+            writeln!(f, "  {}", names.join(" "))?;
+        }
+        writeln!(f, "out of bounds jumps:")?;
+        for (pc, target) in self.out_of_bounds.iter() {
+            writeln!(f, "  {}: {} -> {}", pc, self.instructions[*pc], target)?;
+        }
+        writeln!(f, "instructions:")?;
+        for (pc, verdict) in self.verdicts.iter().enumerate() {
+            writeln!(f, "  {}: {} | {}", pc, self.instructions[pc], verdict)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{Halt, Machine};
+
+    const EXAMPLE: &str = "nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6";
+
+    fn analyze(source: &str) -> Analysis {
+        Analysis::analyze(&source.parse().unwrap())
+    }
+
+    fn block(start: usize, end: usize, exit: Exit) -> Block {
+        Block { start, end, exit }
+    }
+
+    #[test]
+    fn straight_line() {
+        let analysis = analyze("acc +1\nnop +5\nacc -2\n");
+        assert_eq!(analysis.blocks, vec![block(0, 3, Exit::Terminates)]);
+        assert!(analysis.loops.is_empty());
+        assert!(analysis.out_of_bounds.is_empty());
+        assert_eq!(analysis.verdicts, vec![Verdict::AlwaysTerminates; 3]);
+        // a jump to just past the end is how a program terminates
+        let analysis = analyze("jmp +2\nacc +1\n");
+        assert_eq!(analysis.program_verdict(), Verdict::AlwaysTerminates);
+        assert!(analysis.out_of_bounds.is_empty());
+        assert_eq!(analyze("").program_verdict(), Verdict::AlwaysTerminates);
+    }
+
+    #[test]
+    fn self_loop() {
+        let analysis = analyze("nop +0\njmp +0\nacc +1\n");
+        assert_eq!(
+            analysis.blocks,
+            vec![
+                block(0, 1, Exit::Block(1)),
+                block(1, 2, Exit::Block(1)),
+                block(2, 3, Exit::Terminates),
+            ]
+        );
+        assert_eq!(analysis.loops, vec![vec![1]]);
+        assert_eq!(
+            analysis.verdicts,
+            vec![
+                Verdict::AlwaysLoops,
+                Verdict::AlwaysLoops,
+                Verdict::Unreachable
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_loops() {
+        // the inner loop at 2..4 sits inside the outer one at 0..6, which
+        // jumps over it
+        let analysis = analyze("acc +1\njmp +3\nacc +2\njmp -1\nacc +3\njmp -5\nacc +4\n");
+        assert_eq!(
+            analysis.blocks,
+            vec![
+                block(0, 2, Exit::Block(2)),
+                block(2, 4, Exit::Block(1)),
+                block(4, 6, Exit::Block(0)),
+                block(6, 7, Exit::Terminates),
+            ]
+        );
+        assert_eq!(analysis.loops, vec![vec![0, 2], vec![1]]);
+        use Verdict::*;
+        assert_eq!(
+            analysis.verdicts,
+            vec![
+                AlwaysLoops,
+                AlwaysLoops,
+                Unreachable,
+                Unreachable,
+                AlwaysLoops,
+                AlwaysLoops,
+                Unreachable
+            ]
+        );
+        // once the outer loop jumps into the inner one it never comes back out
+        let analysis = analyze("acc +1\njmp +3\nacc +2\njmp -1\nacc +3\njmp -3\nacc +4\n");
+        assert_eq!(analysis.loops, vec![vec![1]]);
+        assert_eq!(
+            analysis.verdicts,
+            vec![
+                AlwaysLoops,
+                AlwaysLoops,
+                AlwaysLoops,
+                AlwaysLoops,
+                AlwaysLoops,
+                AlwaysLoops,
+                Unreachable
+            ]
+        );
+    }
+
+    #[test]
+    fn out_of_range_jumps() {
+        let analysis = analyze("acc +1\njmp -5\n");
+        assert_eq!(analysis.blocks, vec![block(0, 2, Exit::OutOfBounds(-4))]);
+        assert_eq!(analysis.out_of_bounds, vec![(1, -4)]);
+        assert_eq!(analysis.program_verdict(), Verdict::LeavesBounds);
+        // past the end by more than one, and never reached
+        let analysis = analyze("jmp +2\njmp +3\nacc +1\n");
+        assert_eq!(analysis.out_of_bounds, vec![(1, 4)]);
+        assert_eq!(analysis.program_verdict(), Verdict::AlwaysTerminates);
+        assert_eq!(analysis.verdicts[1], Verdict::Unreachable);
+    }
+
+    #[test]
+    fn puzzle_example() {
+        let analysis = analyze(EXAMPLE);
+        assert_eq!(analysis.program_verdict(), Verdict::AlwaysLoops);
+        assert_eq!(
+            analysis.to_string(),
+            "\
+program always loops
+blocks:
+  b0 0..1 -> b1
+  b1 1..3 -> b4
+  b2 3..5 -> b1
+  b3 5..6 -> b4
+  b4 6..8 -> b2
+  b5 8..9 -> end
+loops:
+  b1 b4 b2
+out of bounds jumps:
+instructions:
+  0: nop +0 | always loops
+  1: acc +1 | always loops
+  2: jmp +4 | always loops
+  3: acc +3 | always loops
+  4: jmp -3 | always loops
+  5: acc -99 | unreachable
+  6: acc +1 | always loops
+  7: jmp -4 | always loops
+  8: acc +6 | unreachable
+"
+        );
+        let fixed = EXAMPLE.parse::<Program>().unwrap().with_flipped(7).unwrap();
+        assert_eq!(
+            Analysis::analyze(&fixed).program_verdict(),
+            Verdict::AlwaysTerminates
+        );
+    }
+
+    #[test]
+    fn verdicts_match_the_machine() {
+        // the example with each instruction flipped in turn, and a few more;
+        // the analysis doesn't model the accumulator, so no overflows here
+        let example: Program = EXAMPLE.parse().unwrap();
+        let mut programs: Vec<Program> = (0..example.len())
+            .filter_map(|pc| example.with_flipped(pc))
+            .collect();
+        programs.push(example);
+        for source in ["", "jmp +0", "acc +1\njmp -5", "jmp +2\njmp +3\nacc +1"] {
+            programs.push(source.parse().unwrap());
+        }
+        for program in &programs {
+            let expected = match Machine::new(program).run() {
+                Halt::Terminated => Verdict::AlwaysTerminates,
+                Halt::LoopDetected { .. } => Verdict::AlwaysLoops,
+                Halt::OutOfBounds { .. } => Verdict::LeavesBounds,
+                halt => panic!("{:?}", halt),
+            };
+            assert_eq!(Analysis::analyze(program).program_verdict(), expected);
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use crate::error::{Error, ParseErrorKind};
+use crate::instruction::Instruction;
+use crate::program::Program;
+
+pub const MAGIC: &[u8; 4] = b"D8RM";
+pub const VERSION: u8 = 1;
+
+fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find(['#', ';']) {
+        Some(i) => &line[..i],
+        None => line,
+    }
+}
+
+// Assembles source text into a program. On top of the puzzle format it accepts
+// `#` or `;` comments, `name:` labels (alone on a line or before an instruction)
+// and label names in place of the relative offset of jmp and nop.
+pub fn assemble(source: &str) -> Result<Program, Error> {
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    let mut lines = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let mut text = strip_comment(line).trim();
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if !is_label(label) {
+                return Err(Error::Parse {
+                    line: i + 1,
+                    kind: ParseErrorKind::Syntax(line.to_owned()),
+                });
+            }
+            if labels.insert(label, lines.len()).is_some() {
+                return Err(Error::Parse {
+                    line: i + 1,
+                    kind: ParseErrorKind::DuplicateLabel(label.to_owned()),
+                });
+            }
+            text = text[colon + 1..].trim();
+        }
+        if !text.is_empty() {
+            lines.push((i + 1, text));
+        }
+    }
+
+    let mut instructions = Vec::with_capacity(lines.len());
+    for (pc, &(line, text)) in lines.iter().enumerate() {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let resolved = match words.as_slice() {
+            [op @ "jmp", arg] | [op @ "nop", arg] if is_label(arg) => {
+                let target = *labels.get(arg).ok_or_else(|| Error::Parse {
+                    line,
+                    kind: ParseErrorKind::UndefinedLabel((*arg).to_owned()),
+                })?;
+                let offset =
+                    i32::try_from(target as i64 - pc as i64).map_err(|_| Error::Parse {
+                        line,
+                        kind: ParseErrorKind::BadArgument((*arg).to_owned()),
+                    })?;
+                format!("{} {:+}", op, offset)
+            }
+            _ => text.to_owned(),
+        };
+        let instruction = resolved
+            .parse::<Instruction>()
+            .map_err(|kind| Error::Parse { line, kind })?;
+        instructions.push(instruction);
+    }
+    Ok(Program::new(instructions))
+}
+
+fn zigzag(arg: i32) -> u64 {
+    ((arg << 1) ^ (arg >> 31)) as u32 as u64
+}
+
+fn unzigzag(value: u64) -> i32 {
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+// Binary rom: MAGIC, VERSION, the instruction count as a LEB128 varint, then one
+// varint per instruction holding the zigzagged arg shifted left by two with the
+// opcode (0 nop, 1 jmp, 2 acc) in the low bits. Small offsets take one byte.
+pub fn encode(program: &Program) -> Vec<u8> {
+    let mut out = Vec::with_capacity(MAGIC.len() + 1 + program.len() * 2);
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    write_varint(&mut out, program.len() as u64);
+    for instruction in program.instructions() {
+        let op = match instruction {
+            Instruction::Nop(_) => 0,
+            Instruction::Jmp(_) => 1,
+            Instruction::Acc(_) => 2,
+        };
+        write_varint(&mut out, zigzag(instruction.arg()) << 2 | op);
+    }
+    out
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Program, Error> {
+    if !is_binary(bytes) {
+        return Err(Error::Rom {
+            offset: 0,
+            reason: "missing magic number",
+        });
+    }
+    if bytes[MAGIC.len()] != VERSION {
+        return Err(Error::Rom {
+            offset: MAGIC.len(),
+            reason: "unsupported version",
+        });
+    }
+    let mut offset = MAGIC.len() + 1;
+    let count = read_varint(bytes, &mut offset)?;
+    let mut instructions = Vec::with_capacity(count.min(bytes.len() as u64) as usize);
+    for _ in 0..count {
+        let start = offset;
+        let value = read_varint(bytes, &mut offset)?;
+        if value >> 2 > u64::from(u32::MAX) {
+            return Err(Error::Rom {
+                offset: start,
+                reason: "argument out of range",
+            });
+        }
+        let arg = unzigzag(value >> 2);
+        instructions.push(match value & 3 {
+            0 => Instruction::Nop(arg),
+            1 => Instruction::Jmp(arg),
+            2 => Instruction::Acc(arg),
+            _ => {
+                return Err(Error::Rom {
+                    offset: start,
+                    reason: "unknown opcode",
+                })
+            }
+        });
+    }
+    if offset != bytes.len() {
+        return Err(Error::Rom {
+            offset,
+            reason: "trailing bytes",
+        });
+    }
+    Ok(Program::new(instructions))
+}
+
+// canonical text of a binary rom, readable by `Program::from_str`
+pub fn disassemble(bytes: &[u8]) -> Result<String, Error> {
+    Ok(decode(bytes)?.to_string())
+}
+
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.len() > MAGIC.len() && bytes.starts_with(MAGIC)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], offset: &mut usize) -> Result<u64, Error> {
+    let start = *offset;
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = match bytes.get(*offset) {
+            Some(&byte) => byte,
+            None => {
+                return Err(Error::Rom {
+                    offset: start,
+                    reason: "truncated varint",
+                })
+            }
+        };
+        *offset += 1;
+        if shift > 63 {
+            return Err(Error::Rom {
+                offset: start,
+                reason: "varint too long",
+            });
+        }
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom_error(offset: usize, reason: &'static str) -> Result<Program, Error> {
+        Err(Error::Rom { offset, reason })
+    }
+
+    #[test]
+    fn labels_and_comments() {
+        let program = assemble(
+            "# counts down\n\
+             start: acc +1 ; one more\n\
+             nop end\n\
+             loop:\n\
+             jmp start\n\
+             end: acc -1\n",
+        )
+        .unwrap();
+        assert_eq!(program.to_string(), "acc +1\nnop +2\njmp -2\nacc -1\n");
+    }
+
+    #[test]
+    fn round_trip() {
+        let program =
+            assemble("a: nop +0\nacc +1\njmp a\nacc -2147483648\nnop +2147483647\n").unwrap();
+        let bytes = encode(&program);
+        assert_eq!(
+            bytes[..9],
+            [
+                b'D',
+                b'8',
+                b'R',
+                b'M',
+                VERSION,
+                5,
+                0,
+                2 << 2 | 2,
+                3 << 2 | 1
+            ]
+        );
+        assert!(is_binary(&bytes));
+        assert_eq!(decode(&bytes), Ok(program.clone()));
+        assert_eq!(disassemble(&bytes).unwrap(), program.to_string());
+        assert_eq!(assemble(&program.to_string()), Ok(program));
+        assert_eq!(decode(&encode(&Program::default())), Ok(Program::default()));
+    }
+
+    #[test]
+    fn undefined_label() {
+        assert_eq!(
+            assemble("nop +0\njmp nowhere\n"),
+            Err(Error::Parse {
+                line: 2,
+                kind: ParseErrorKind::UndefinedLabel("nowhere".to_owned())
+            })
+        );
+    }
+
+    #[test]
+    fn duplicate_label() {
+        assert_eq!(
+            assemble("top: nop +0\ntop:\nacc +1\n"),
+            Err(Error::Parse {
+                line: 2,
+                kind: ParseErrorKind::DuplicateLabel("top".to_owned())
+            })
+        );
+        assert_eq!(
+            assemble("1st: nop +0\n"),
+            Err(Error::Parse {
+                line: 1,
+                kind: ParseErrorKind::Syntax("1st: nop +0".to_owned())
+            })
+        );
+    }
+
+    #[test]
+    fn truncated_rom() {
+        let bytes = encode(&"acc +1000\njmp -1\n".parse().unwrap());
+        // the header, a count of 2, then two bytes for the acc and one for the jmp
+        assert_eq!(bytes.len(), 9);
+        for len in 0..MAGIC.len() + 1 {
+            assert_eq!(decode(&bytes[..len]), rom_error(0, "missing magic number"));
+        }
+        assert_eq!(decode(&bytes[..5]), rom_error(5, "truncated varint"));
+        assert_eq!(decode(&bytes[..6]), rom_error(6, "truncated varint"));
+        assert_eq!(decode(&bytes[..7]), rom_error(6, "truncated varint"));
+        assert_eq!(decode(&bytes[..8]), rom_error(8, "truncated varint"));
+        let mut long = bytes.clone();
+        long.push(0);
+        assert_eq!(decode(&long), rom_error(9, "trailing bytes"));
+    }
+
+    #[test]
+    fn wrong_header() {
+        let mut bytes = encode(&"nop +0\n".parse().unwrap());
+        bytes[3] = b'X';
+        assert!(!is_binary(&bytes));
+        assert_eq!(decode(&bytes), rom_error(0, "missing magic number"));
+        bytes[3] = b'M';
+        bytes[4] = VERSION + 1;
+        assert_eq!(decode(&bytes), rom_error(4, "unsupported version"));
+    }
+
+    #[test]
+    fn bad_instructions() {
+        let header = [b'D', b'8', b'R', b'M', VERSION, 1];
+        assert_eq!(
+            decode(&[&header[..], &[3]].concat()),
+            rom_error(6, "unknown opcode")
+        );
+        // a zigzagged arg past 32 bits
+        let mut bytes = header.to_vec();
+        write_varint(&mut bytes, 1 << 34);
+        assert_eq!(decode(&bytes), rom_error(6, "argument out of range"));
+        let mut bytes = header.to_vec();
+        bytes.extend_from_slice(&[0xff; 10]);
+        bytes.push(0);
+        assert_eq!(decode(&bytes), rom_error(6, "varint too long"));
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::io::prelude::*;
+
+use crate::machine::{Halt, Machine};
+use crate::program::Program;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Stepped,
+    Halted(Halt),
+    Breakpoint(usize),
+    // acc moved onto or across `value` while running the instruction at `pc`
+    Watchpoint {
+        pc: usize,
+        value: i64,
+        from: i64,
+        to: i64,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Debugger<'a> {
+    machine: Machine<'a>,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<i64>,
+    trace: Vec<usize>,
+}
+
+fn crossed(value: i64, from: i64, to: i64) -> bool {
+    (from < value && to >= value) || (from > value && to <= value)
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(program: &'a Program) -> Debugger<'a> {
+        Debugger {
+            machine: Machine::new(program),
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn machine(&self) -> &Machine<'a> {
+        &self.machine
+    }
+
+    // pcs in the order they were executed
+    pub fn trace(&self) -> &[usize] {
+        &self.trace
+    }
+
+    pub fn add_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.insert(pc)
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    pub fn add_watchpoint(&mut self, value: i64) -> bool {
+        self.watchpoints.insert(value)
+    }
+
+    pub fn remove_watchpoint(&mut self, value: i64) -> bool {
+        self.watchpoints.remove(&value)
+    }
+
+    // starts the program over, keeping breakpoints and watchpoints
+    pub fn reset(&mut self) {
+        self.machine = Machine::new(self.machine.program());
+        self.trace.clear();
+    }
+
+    pub fn step(&mut self) -> Stop {
+        if let Some(halt) = self.machine.halted() {
+            return Stop::Halted(halt);
+        }
+        let pc = self.machine.pc() as usize;
+        let from = self.machine.acc();
+        if let Some(halt) = self.machine.step() {
+            return Stop::Halted(halt);
+        }
+        self.trace.push(pc);
+        let to = self.machine.acc();
+        match self
+            .watchpoints
+            .iter()
+            .find(|&&value| crossed(value, from, to))
+        {
+            Some(&value) => Stop::Watchpoint {
+                pc,
+                value,
+                from,
+                to,
+            },
+            None => Stop::Stepped,
+        }
+    }
+
+    // runs until a breakpoint, a watchpoint or a halt; the instruction under the
+    // current pc always runs so continuing from a breakpoint makes progress
+    pub fn cont(&mut self) -> Stop {
+        let mut stop = self.step();
+        while stop == Stop::Stepped {
+            let pc = self.machine.pc();
+            if pc >= 0
+                && self.breakpoints.contains(&(pc as usize))
+                && self.machine.halted().is_none()
+            {
+                return Stop::Breakpoint(pc as usize);
+            }
+            stop = self.step();
+        }
+        stop
+    }
+
+    fn location(&self) -> String {
+        let pc = self.machine.pc();
+        let current = if pc >= 0 {
+            self.machine
+                .program()
+                .get(pc as usize)
+                .map(|i| i.to_string())
+        } else {
+            None
+        };
+        format!(
+            "pc={} acc={} | {}",
+            pc,
+            self.machine.acc(),
+            current.unwrap_or_else(|| "-".to_owned())
+        )
+    }
+
+    fn report<W: Write>(&self, stop: Stop, out: &mut W) -> io::Result<()> {
+        match stop {
+            Stop::Stepped => {}
+            Stop::Halted(Halt::Terminated) => writeln!(out, "terminated")?,
+            Stop::Halted(Halt::LoopDetected { pc, .. }) => {
+                writeln!(out, "loop detected: pc {} would run a second time", pc)?
+            }
+            Stop::Halted(Halt::OutOfBounds { pc }) => {
+                writeln!(out, "jumped out of bounds to {}", pc)?
+            }
+            Stop::Halted(Halt::Overflow { pc }) => writeln!(out, "overflow at pc {}", pc)?,
+            Stop::Halted(Halt::StateLimit { pc }) => {
+                writeln!(out, "gave up at pc {}: too many states", pc)?
+            }
+            Stop::Breakpoint(pc) => writeln!(out, "breakpoint at {}", pc)?,
+            Stop::Watchpoint {
+                pc,
+                value,
+                from,
+                to,
+            } => writeln!(
+                out,
+                "watchpoint {}: acc {} -> {} at pc {}",
+                value, from, to, pc
+            )?,
+        }
+        writeln!(out, "{}", self.location())
+    }
+
+    // Runs one command line and writes its output. Returns Ok(false) on quit.
+    //
+    //   step [n] | s     run n instructions (default 1)
+    //   continue | c     run to the next breakpoint, watchpoint or halt
+    //   break <pc> | b   stop before running pc; delete <pc> removes it
+    //   watch <value>    stop when acc reaches or crosses value; unwatch <value>
+    //   trace [n]        last n executed pcs (default all)
+    //   print | p        current pc, acc and instruction
+    //   reset            start over, keeping breakpoints and watchpoints
+    //   quit | q
+    pub fn execute<W: Write>(&mut self, command: &str, out: &mut W) -> io::Result<bool> {
+        let command = command.trim();
+        if command.is_empty() || command.starts_with('#') {
+            return Ok(true);
+        }
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap();
+        let arg = words.next();
+        match (name, arg) {
+            ("step", n) | ("s", n) => {
+                let n = match n.map(|n| n.parse::<usize>()) {
+                    None => 1,
+                    Some(Ok(n)) => n,
+                    Some(Err(_)) => return usage(out, "step [n]"),
+                };
+                let mut stop = Stop::Stepped;
+                for _ in 0..n {
+                    stop = self.step();
+                    if stop != Stop::Stepped {
+                        break;
+                    }
+                }
+                self.report(stop, out)?;
+            }
+            ("continue", None) | ("c", None) => {
+                let stop = self.cont();
+                self.report(stop, out)?;
+            }
+            ("break", Some(pc)) | ("b", Some(pc)) => match pc.parse() {
+                Ok(pc) => {
+                    self.add_breakpoint(pc);
+                    writeln!(out, "breakpoint set at {}", pc)?;
+                }
+                Err(_) => return usage(out, "break <pc>"),
+            },
+            ("delete", Some(pc)) => match pc.parse() {
+                Ok(pc) if self.remove_breakpoint(pc) => {
+                    writeln!(out, "breakpoint at {} deleted", pc)?
+                }
+                Ok(pc) => writeln!(out, "no breakpoint at {}", pc)?,
+                Err(_) => return usage(out, "delete <pc>"),
+            },
+            ("watch", Some(value)) | ("w", Some(value)) => match value.parse() {
+                Ok(value) => {
+                    self.add_watchpoint(value);
+                    writeln!(out, "watching acc for {}", value)?;
+                }
+                Err(_) => return usage(out, "watch <value>"),
+            },
+            ("unwatch", Some(value)) => match value.parse() {
+                Ok(value) if self.remove_watchpoint(value) => {
+                    writeln!(out, "watchpoint {} deleted", value)?
+                }
+                Ok(value) => writeln!(out, "no watchpoint for {}", value)?,
+                Err(_) => return usage(out, "unwatch <value>"),
+            },
+            ("trace", n) => {
+                let n = match n.map(|n| n.parse::<usize>()) {
+                    None => self.trace.len(),
+                    Some(Ok(n)) => n.min(self.trace.len()),
+                    Some(Err(_)) => return usage(out, "trace [n]"),
+                };
+                let pcs: Vec<String> = self.trace[self.trace.len() - n..]
+                    .iter()
+                    .map(|pc| pc.to_string())
+                    .collect();
+                writeln!(out, "{}", pcs.join(" "))?;
+            }
+            ("print", None) | ("p", None) => writeln!(out, "{}", self.location())?,
+            ("reset", None) => {
+                self.reset();
+                writeln!(out, "{}", self.location())?;
+            }
+            ("quit", None) | ("q", None) => return Ok(false),
+            _ => writeln!(out, "unknown command: {}", command)?,
+        }
+        Ok(true)
+    }
+
+    // Feeds every line of `input` to `execute`. With `prompt` set a "(day8) " prompt
+    // is written before each command, for interactive sessions.
+    pub fn run_script<R: BufRead, W: Write>(
+        &mut self,
+        input: R,
+        out: &mut W,
+        prompt: bool,
+    ) -> io::Result<()> {
+        if prompt {
+            write!(out, "(day8) ")?;
+            out.flush()?;
+        }
+        for line in input.lines() {
+            if !self.execute(&line?, out)? {
+                break;
+            }
+            if prompt {
+                write!(out, "(day8) ")?;
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn usage<W: Write>(out: &mut W, usage: &str) -> io::Result<bool> {
+    writeln!(out, "usage: {}", usage)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6";
+
+    fn session(source: &str, commands: &str) -> String {
+        let program: Program = source.parse().unwrap();
+        let mut out = Vec::new();
+        Debugger::new(&program)
+            .run_script(commands.as_bytes(), &mut out, false)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn steps_and_traces() {
+        assert_eq!(
+            session(EXAMPLE, "s\nstep 2\ntrace\ntrace 1\np\n"),
+            "pc=1 acc=0 | acc +1\n\
+             pc=6 acc=1 | acc +1\n\
+             0 1 2\n\
+             2\n\
+             pc=6 acc=1 | acc +1\n"
+        );
+    }
+
+    #[test]
+    fn continues_to_breakpoints_and_the_loop() {
+        assert_eq!(
+            session(EXAMPLE, "b 4\nc\nc\ndelete 4\ndelete 4\nreset\nc\ntrace\n"),
+            "breakpoint set at 4\n\
+             breakpoint at 4\n\
+             pc=4 acc=5 | jmp -3\n\
+             loop detected: pc 1 would run a second time\n\
+             pc=1 acc=5 | acc +1\n\
+             breakpoint at 4 deleted\n\
+             no breakpoint at 4\n\
+             pc=0 acc=0 | nop +0\n\
+             loop detected: pc 1 would run a second time\n\
+             pc=1 acc=5 | acc +1\n\
+             0 1 2 6 7 3 4\n"
+        );
+    }
+
+    #[test]
+    fn breakpoint_under_pc_still_moves() {
+        let program: Program = EXAMPLE.parse().unwrap();
+        let mut debugger = Debugger::new(&program);
+        debugger.add_breakpoint(0);
+        debugger.add_breakpoint(2);
+        assert_eq!(debugger.cont(), Stop::Breakpoint(2));
+        assert_eq!(debugger.trace(), &[0, 1]);
+    }
+
+    #[test]
+    fn watchpoints_stop_on_crossing() {
+        assert_eq!(
+            session(EXAMPLE, "watch 3\nc\nc\nunwatch 3\nunwatch 3\n"),
+            "watching acc for 3\n\
+             watchpoint 3: acc 2 -> 5 at pc 3\n\
+             pc=4 acc=5 | jmp -3\n\
+             loop detected: pc 1 would run a second time\n\
+             pc=1 acc=5 | acc +1\n\
+             watchpoint 3 deleted\n\
+             no watchpoint for 3\n"
+        );
+        let program: Program = "acc +5\nacc -10\nacc +3".parse().unwrap();
+        let mut debugger = Debugger::new(&program);
+        debugger.add_watchpoint(0);
+        assert_eq!(debugger.step(), Stop::Stepped);
+        assert_eq!(
+            debugger.step(),
+            Stop::Watchpoint {
+                pc: 1,
+                value: 0,
+                from: 5,
+                to: -5
+            }
+        );
+        assert_eq!(debugger.step(), Stop::Stepped);
+        assert_eq!(debugger.step(), Stop::Halted(Halt::Terminated));
+    }
+
+    #[test]
+    fn halts_are_reported() {
+        assert_eq!(
+            session("acc +1\njmp -5\n", "c\ns\n"),
+            "jumped out of bounds to -4\npc=-4 acc=1 | -\n\
+             jumped out of bounds to -4\npc=-4 acc=1 | -\n"
+        );
+        assert_eq!(session("acc +2\n", "s 5\n"), "terminated\npc=1 acc=2 | -\n");
+    }
+
+    #[test]
+    fn bad_commands_print_usage() {
+        assert_eq!(
+            session(EXAMPLE, "step x\nb\nwatch +\nfly\n# comment\n\nq\np\n"),
+            "usage: step [n]\n\
+             unknown command: b\n\
+             usage: watch <value>\n\
+             unknown command: fly\n"
+        );
+    }
+}
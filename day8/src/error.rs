@@ -0,0 +1,39 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    Syntax(String),
+    UnknownOpcode(String),
+    BadArgument(String),
+    UndefinedLabel(String),
+    DuplicateLabel(String),
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::Syntax(text) => write!(f, "expected `<op> <+/-arg>`, found {:?}", text),
+            ParseErrorKind::UnknownOpcode(op) => write!(f, "unknown opcode {:?}", op),
+            ParseErrorKind::BadArgument(arg) => write!(f, "bad argument {:?}", arg),
+            ParseErrorKind::UndefinedLabel(label) => write!(f, "undefined label {:?}", label),
+            ParseErrorKind::DuplicateLabel(label) => write!(f, "label {:?} defined twice", label),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Parse { line: usize, kind: ParseErrorKind },
+    Rom { offset: usize, reason: &'static str },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse { line, kind } => write!(f, "line {}: {}", line, kind),
+            Error::Rom { offset, reason } => write!(f, "byte {}: {}", offset, reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
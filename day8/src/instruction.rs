@@ -0,0 +1,74 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::ParseErrorKind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Nop(i32),
+    Jmp(i32),
+    Acc(i32),
+}
+
+impl Instruction {
+    pub fn arg(&self) -> i32 {
+        match *self {
+            Instruction::Nop(arg) | Instruction::Jmp(arg) | Instruction::Acc(arg) => arg,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match *self {
+            Instruction::Nop(_) => "nop",
+            Instruction::Jmp(_) => "jmp",
+            Instruction::Acc(_) => "acc",
+        }
+    }
+
+    // pc of the instruction that runs after this one when it sits at `pc`
+    pub fn next_pc(&self, pc: i64) -> i64 {
+        match *self {
+            Instruction::Jmp(arg) => pc + i64::from(arg),
+            _ => pc + 1,
+        }
+    }
+
+    // the corruption in part two only ever swaps a jmp with a nop
+    pub fn flipped(&self) -> Option<Instruction> {
+        match *self {
+            Instruction::Nop(arg) => Some(Instruction::Jmp(arg)),
+            Instruction::Jmp(arg) => Some(Instruction::Nop(arg)),
+            Instruction::Acc(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:+}", self.mnemonic(), self.arg())
+    }
+}
+
+impl FromStr for Instruction {
+    type Err = ParseErrorKind;
+
+    fn from_str(s: &str) -> Result<Instruction, ParseErrorKind> {
+        let mut parts = s.split_whitespace();
+        let (op, arg) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(op), Some(arg), None) => (op, arg),
+            _ => return Err(ParseErrorKind::Syntax(s.to_owned())),
+        };
+        if !arg.starts_with('+') && !arg.starts_with('-') {
+            return Err(ParseErrorKind::BadArgument(arg.to_owned()));
+        }
+        let arg = arg
+            .parse::<i32>()
+            .map_err(|_| ParseErrorKind::BadArgument(arg.to_owned()))?;
+        match op {
+            "nop" => Ok(Instruction::Nop(arg)),
+            "jmp" => Ok(Instruction::Jmp(arg)),
+            "acc" => Ok(Instruction::Acc(arg)),
+            _ => Err(ParseErrorKind::UnknownOpcode(op.to_owned())),
+        }
+    }
+}
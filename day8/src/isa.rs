@@ -0,0 +1,656 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::error::{Error, ParseErrorKind};
+use crate::instruction::Instruction;
+use crate::machine::Halt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Imm(i64),
+    // index into the rom's `Registers`
+    Reg(usize),
+}
+
+impl Operand {
+    pub fn parse(s: &str, registers: &mut Registers) -> Result<Operand, ParseErrorKind> {
+        if let Ok(value) = s.parse::<i64>() {
+            Ok(Operand::Imm(value))
+        } else {
+            register_arg(s, registers).map(Operand::Reg)
+        }
+    }
+
+    pub fn value(&self, state: &State) -> i64 {
+        match self {
+            Operand::Imm(value) => *value,
+            Operand::Reg(index) => state.register(*index),
+        }
+    }
+}
+
+fn is_register(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+}
+
+pub fn register_arg(s: &str, registers: &mut Registers) -> Result<usize, ParseErrorKind> {
+    if is_register(s) {
+        Ok(registers.index(s))
+    } else {
+        Err(ParseErrorKind::BadArgument(s.to_owned()))
+    }
+}
+
+// the register `acc` instructions add to, and the one `Machine` reports
+pub const ACC: usize = 0;
+
+// Register names in the order a rom first mentions them, `acc` first.
+// Operations are decoded to indices so running them never looks up a name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registers {
+    names: Vec<String>,
+}
+
+impl Registers {
+    pub fn new() -> Registers {
+        Registers {
+            names: vec!["acc".to_owned()],
+        }
+    }
+
+    // the index of `name`, giving it the next one if it is new
+    pub fn index(&mut self, name: &str) -> usize {
+        match self.get(name) {
+            Some(index) => index,
+            None => {
+                self.names.push(name.to_owned());
+                self.names.len() - 1
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+impl Default for Registers {
+    fn default() -> Registers {
+        Registers::new()
+    }
+}
+
+pub fn offset_arg(s: &str) -> Result<i64, ParseErrorKind> {
+    s.parse::<i64>()
+        .map_err(|_| ParseErrorKind::BadArgument(s.to_owned()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Next,
+    Jump(i64),
+    // the result didn't fit in an i64; nothing was written and the cpu halts
+    Overflow,
+}
+
+pub trait Operation: fmt::Debug {
+    fn execute(&self, state: &mut State) -> Flow;
+}
+
+impl<O: Operation + ?Sized> Operation for Box<O> {
+    fn execute(&self, state: &mut State) -> Flow {
+        (**self).execute(state)
+    }
+}
+
+// The puzzle's own instructions run on the cpu as they are, which is how
+// `Machine` works; `acc` is the same register the base set's acc adds to.
+impl Operation for Instruction {
+    fn execute(&self, state: &mut State) -> Flow {
+        match *self {
+            Instruction::Nop(_) => Flow::Next,
+            Instruction::Jmp(offset) => Flow::Jump(i64::from(offset)),
+            Instruction::Acc(value) => state.update(ACC, |acc| acc.checked_add(i64::from(value))),
+        }
+    }
+}
+
+pub type Decoder =
+    Box<dyn Fn(&[&str], &mut Registers) -> Result<Box<dyn Operation>, ParseErrorKind>>;
+
+// Maps mnemonics to decoders; the cpu only ever sees `Operation`s, so adding an
+// opcode is a `register` call. Decoders turn register names into indices
+// through the `Registers` of the rom being parsed.
+pub struct InstructionSet {
+    decoders: HashMap<String, Decoder>,
+}
+
+impl InstructionSet {
+    pub fn new() -> InstructionSet {
+        InstructionSet {
+            decoders: HashMap::new(),
+        }
+    }
+
+    pub fn register<F>(&mut self, mnemonic: &str, decoder: F)
+    where
+        F: Fn(&[&str], &mut Registers) -> Result<Box<dyn Operation>, ParseErrorKind> + 'static,
+    {
+        self.decoders.insert(mnemonic.to_owned(), Box::new(decoder));
+    }
+
+    pub fn contains(&self, mnemonic: &str) -> bool {
+        self.decoders.contains_key(mnemonic)
+    }
+
+    // nop/jmp/acc with the puzzle's semantics
+    pub fn base() -> InstructionSet {
+        let mut isa = InstructionSet::new();
+        isa.register("nop", |args, _| {
+            let [arg] = arity::<1>(args)?;
+            offset_arg(arg)?;
+            Ok(Box::new(Nop))
+        });
+        isa.register("jmp", |args, _| {
+            let [offset] = arity::<1>(args)?;
+            Ok(Box::new(Jmp(offset_arg(offset)?)))
+        });
+        isa.register("acc", |args, _| {
+            let [value] = arity::<1>(args)?;
+            Ok(Box::new(Add(ACC, Operand::Imm(offset_arg(value)?))))
+        });
+        isa
+    }
+
+    // base plus set/add/mul on named registers, jz/jnz and out
+    pub fn extended() -> InstructionSet {
+        let mut isa = InstructionSet::base();
+        isa.register("set", |args, registers| {
+            let [reg, value] = arity::<2>(args)?;
+            let reg = register_arg(reg, registers)?;
+            Ok(Box::new(Set(reg, Operand::parse(value, registers)?)))
+        });
+        isa.register("add", |args, registers| {
+            let [reg, value] = arity::<2>(args)?;
+            let reg = register_arg(reg, registers)?;
+            Ok(Box::new(Add(reg, Operand::parse(value, registers)?)))
+        });
+        isa.register("mul", |args, registers| {
+            let [reg, value] = arity::<2>(args)?;
+            let reg = register_arg(reg, registers)?;
+            Ok(Box::new(Mul(reg, Operand::parse(value, registers)?)))
+        });
+        isa.register("jz", |args, registers| {
+            let [value, offset] = arity::<2>(args)?;
+            Ok(Box::new(JumpIf {
+                value: Operand::parse(value, registers)?,
+                offset: offset_arg(offset)?,
+                zero: true,
+            }))
+        });
+        isa.register("jnz", |args, registers| {
+            let [value, offset] = arity::<2>(args)?;
+            Ok(Box::new(JumpIf {
+                value: Operand::parse(value, registers)?,
+                offset: offset_arg(offset)?,
+                zero: false,
+            }))
+        });
+        isa.register("out", |args, registers| {
+            let [value] = arity::<1>(args)?;
+            Ok(Box::new(Out(Operand::parse(value, registers)?)))
+        });
+        isa
+    }
+
+    pub fn decode(
+        &self,
+        line: &str,
+        registers: &mut Registers,
+    ) -> Result<Box<dyn Operation>, ParseErrorKind> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (mnemonic, args) = match words.split_first() {
+            Some((mnemonic, args)) => (*mnemonic, args),
+            None => return Err(ParseErrorKind::Syntax(line.to_owned())),
+        };
+        match self.decoders.get(mnemonic) {
+            Some(decoder) => decoder(args, registers),
+            None => Err(ParseErrorKind::UnknownOpcode(mnemonic.to_owned())),
+        }
+    }
+
+    // blank lines are skipped, errors report the 1-based source line
+    pub fn parse(&self, source: &str) -> Result<Rom, Error> {
+        let mut operations = Vec::new();
+        let mut registers = Registers::new();
+        for (i, line) in source.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let operation = self
+                .decode(line, &mut registers)
+                .map_err(|kind| Error::Parse { line: i + 1, kind })?;
+            operations.push(operation);
+        }
+        Ok(Rom {
+            operations,
+            registers,
+        })
+    }
+}
+
+impl Default for InstructionSet {
+    fn default() -> InstructionSet {
+        InstructionSet::base()
+    }
+}
+
+pub fn arity<'a, const N: usize>(args: &[&'a str]) -> Result<[&'a str; N], ParseErrorKind> {
+    <[&str; N]>::try_from(args).map_err(|_| ParseErrorKind::Syntax(args.join(" ")))
+}
+
+#[derive(Debug)]
+pub struct Rom {
+    operations: Vec<Box<dyn Operation>>,
+    registers: Registers,
+}
+
+impl Rom {
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+}
+
+#[derive(Debug)]
+struct Nop;
+
+impl Operation for Nop {
+    fn execute(&self, _: &mut State) -> Flow {
+        Flow::Next
+    }
+}
+
+#[derive(Debug)]
+struct Jmp(i64);
+
+impl Operation for Jmp {
+    fn execute(&self, _: &mut State) -> Flow {
+        Flow::Jump(self.0)
+    }
+}
+
+#[derive(Debug)]
+struct Set(usize, Operand);
+
+impl Operation for Set {
+    fn execute(&self, state: &mut State) -> Flow {
+        let value = self.1.value(state);
+        state.set_register(self.0, value);
+        Flow::Next
+    }
+}
+
+#[derive(Debug)]
+struct Add(usize, Operand);
+
+impl Operation for Add {
+    fn execute(&self, state: &mut State) -> Flow {
+        let value = self.1.value(state);
+        state.update(self.0, |old| old.checked_add(value))
+    }
+}
+
+#[derive(Debug)]
+struct Mul(usize, Operand);
+
+impl Operation for Mul {
+    fn execute(&self, state: &mut State) -> Flow {
+        let value = self.1.value(state);
+        state.update(self.0, |old| old.checked_mul(value))
+    }
+}
+
+#[derive(Debug)]
+struct JumpIf {
+    value: Operand,
+    offset: i64,
+    zero: bool,
+}
+
+impl Operation for JumpIf {
+    fn execute(&self, state: &mut State) -> Flow {
+        if (self.value.value(state) == 0) == self.zero {
+            Flow::Jump(self.offset)
+        } else {
+            Flow::Next
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Out(Operand);
+
+impl Operation for Out {
+    fn execute(&self, state: &mut State) -> Flow {
+        let value = self.0.value(state);
+        state.output.push(value);
+        Flow::Next
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopCheck {
+    // the puzzle rule: stop before any instruction runs a second time
+    Revisit,
+    // stop when pc and every register repeat, which lets jz/jnz loops count down
+    State,
+}
+
+// What operations can see: the registers and what was written with `out`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    registers: Vec<i64>,
+    output: Vec<i64>,
+}
+
+impl State {
+    // `count` registers, all 0
+    pub fn new(count: usize) -> State {
+        State {
+            registers: vec![0; count],
+            output: Vec::new(),
+        }
+    }
+
+    pub fn register(&self, index: usize) -> i64 {
+        self.registers[index]
+    }
+
+    pub fn set_register(&mut self, index: usize, value: i64) {
+        self.registers[index] = value;
+    }
+
+    // writes f of the register back, or leaves it alone when f overflows
+    pub fn update<F: FnOnce(i64) -> Option<i64>>(&mut self, index: usize, f: F) -> Flow {
+        match f(self.registers[index]) {
+            Some(value) => {
+                self.registers[index] = value;
+                Flow::Next
+            }
+            None => Flow::Overflow,
+        }
+    }
+
+    pub fn registers(&self) -> &[i64] {
+        &self.registers
+    }
+
+    pub fn output(&self) -> &[i64] {
+        &self.output
+    }
+}
+
+// Distinct states `LoopCheck::State` remembers before giving up. Each one is
+// a 16 byte fingerprint, so the set stays within a few hundred megabytes.
+pub const MAX_STATES: usize = 1 << 24;
+
+// Runs any slice of operations: a `Rom` decoded through an instruction set, or
+// a `Program`'s instructions as they are.
+#[derive(Debug, Clone)]
+pub struct Cpu<'a, O: Operation = Box<dyn Operation>> {
+    code: &'a [O],
+    pc: i64,
+    state: State,
+    check: LoopCheck,
+    visited: Vec<bool>,
+    seen: HashSet<u128>,
+    stopped: Option<Halt>,
+}
+
+impl<'a> Cpu<'a> {
+    pub fn new(rom: &'a Rom, check: LoopCheck) -> Cpu<'a> {
+        Cpu::load(&rom.operations, rom.registers.len(), check)
+    }
+}
+
+impl<'a, O: Operation> Cpu<'a, O> {
+    pub fn load(code: &'a [O], registers: usize, check: LoopCheck) -> Cpu<'a, O> {
+        Cpu {
+            code,
+            pc: 0,
+            state: State::new(registers.max(ACC + 1)),
+            check,
+            visited: vec![false; code.len()],
+            seen: HashSet::new(),
+            stopped: None,
+        }
+    }
+
+    pub fn code(&self) -> &'a [O] {
+        self.code
+    }
+
+    pub fn pc(&self) -> i64 {
+        self.pc
+    }
+
+    pub fn register(&self, index: usize) -> i64 {
+        self.state.register(index)
+    }
+
+    pub fn registers(&self) -> &[i64] {
+        self.state.registers()
+    }
+
+    pub fn output(&self) -> &[i64] {
+        self.state.output()
+    }
+
+    pub fn visited(&self, pc: usize) -> bool {
+        self.visited.get(pc).copied().unwrap_or(false)
+    }
+
+    // pc and the registers folded into 128 bits, two hashes of 64; telling
+    // states apart this way is wrong with odds far below anything a run can
+    // reach, and keeps what is remembered per state fixed
+    fn fingerprint(&self) -> u128 {
+        let mut low = DefaultHasher::new();
+        let mut high = DefaultHasher::new();
+        high.write_u8(1);
+        (self.pc, &self.state.registers).hash(&mut low);
+        (self.pc, &self.state.registers).hash(&mut high);
+        (u128::from(high.finish()) << 64) | u128::from(low.finish())
+    }
+
+    // the halts that don't depend on the loop check
+    fn stopped(&self) -> Option<Halt> {
+        let len = self.code.len() as i64;
+        if self.stopped.is_some() {
+            self.stopped
+        } else if self.pc == len {
+            Some(Halt::Terminated)
+        } else if self.pc < 0 || self.pc > len {
+            Some(Halt::OutOfBounds { pc: self.pc })
+        } else {
+            None
+        }
+    }
+
+    fn looped(&self, pc: usize) -> Halt {
+        Halt::LoopDetected {
+            pc,
+            acc: self.register(ACC),
+        }
+    }
+
+    // why the cpu would stop instead of running the instruction at pc
+    pub fn halted(&self) -> Option<Halt> {
+        self.stopped().or_else(|| {
+            let pc = self.pc as usize;
+            let repeated = match self.check {
+                LoopCheck::Revisit => self.visited[pc],
+                LoopCheck::State => self.seen.contains(&self.fingerprint()),
+            };
+            if repeated {
+                Some(self.looped(pc))
+            } else if self.check == LoopCheck::State && self.seen.len() == MAX_STATES {
+                Some(Halt::StateLimit { pc })
+            } else {
+                None
+            }
+        })
+    }
+
+    // Records the state at pc, true if it was seen before. Kept out of line
+    // so `step` stays small enough to inline into `run`.
+    #[inline(never)]
+    fn remember(&mut self, pc: usize) -> bool {
+        let key = self.fingerprint();
+        if !self.seen.contains(&key) && self.seen.len() == MAX_STATES {
+            self.stopped = Some(Halt::StateLimit { pc });
+            return false;
+        }
+        !self.seen.insert(key)
+    }
+
+    // Runs a single instruction, or returns the halt reason without touching
+    // the state. An instruction that overflows leaves its register and pc as
+    // they were and halts the cpu, and so does running out of room for states.
+    #[inline(always)]
+    pub fn step(&mut self) -> Option<Halt> {
+        if let Some(halt) = self.stopped() {
+            return Some(halt);
+        }
+        let pc = self.pc as usize;
+        let repeated = match self.check {
+            LoopCheck::Revisit => std::mem::replace(&mut self.visited[pc], true),
+            LoopCheck::State => self.remember(pc),
+        };
+        if self.stopped.is_some() {
+            return self.stopped;
+        }
+        if repeated {
+            return Some(self.looped(pc));
+        }
+        let next = match self.code[pc].execute(&mut self.state) {
+            Flow::Next => self.pc.checked_add(1),
+            Flow::Jump(offset) => self.pc.checked_add(offset),
+            Flow::Overflow => None,
+        };
+        match next {
+            Some(next) => {
+                self.pc = next;
+                None
+            }
+            None => {
+                self.stopped = Some(Halt::Overflow { pc });
+                self.stopped
+            }
+        }
+    }
+
+    pub fn run(&mut self) -> Halt {
+        loop {
+            if let Some(halt) = self.step() {
+                return halt;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::Machine;
+    use crate::program::Program;
+
+    fn run(source: &str) -> (Halt, Vec<i64>) {
+        let rom = InstructionSet::extended().parse(source).unwrap();
+        let mut cpu = Cpu::new(&rom, LoopCheck::State);
+        let halt = cpu.run();
+        (halt, cpu.output().to_vec())
+    }
+
+    #[test]
+    fn overflow_halts() {
+        let max = i64::MAX;
+        let (halt, out) = run(&format!("set a {}\nout a\nadd a +1\nout a", max));
+        assert_eq!(halt, Halt::Overflow { pc: 2 });
+        assert_eq!(out, vec![max]);
+        let (halt, _) = run(&format!("set a {}\nmul a -2", i64::MIN));
+        assert_eq!(halt, Halt::Overflow { pc: 1 });
+        let (halt, _) = run(&format!("nop +0\njmp {}", max));
+        assert_eq!(halt, Halt::Overflow { pc: 1 });
+        let (halt, out) = run("set a -3\nmul a -3\nadd a a\nout a");
+        assert_eq!(halt, Halt::Terminated);
+        assert_eq!(out, vec![18]);
+    }
+
+    #[test]
+    fn overflow_keeps_the_register() {
+        let rom = InstructionSet::extended()
+            .parse(&format!("set a {}\nadd a +1", i64::MAX))
+            .unwrap();
+        let mut cpu = Cpu::new(&rom, LoopCheck::Revisit);
+        assert_eq!(cpu.run(), Halt::Overflow { pc: 1 });
+        let a = rom.registers().get("a").unwrap();
+        assert_eq!(cpu.register(a), i64::MAX);
+        assert_eq!(cpu.pc(), 1);
+        assert_eq!(cpu.step(), Some(Halt::Overflow { pc: 1 }));
+        assert_eq!(cpu.halted(), Some(Halt::Overflow { pc: 1 }));
+    }
+
+    #[test]
+    fn base_set_matches_the_machine() {
+        let example = "nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6";
+        let program: Program = example.parse().unwrap();
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.run(), Halt::LoopDetected { pc: 1, acc: 5 });
+        let rom = InstructionSet::base().parse(example).unwrap();
+        let mut cpu = Cpu::new(&rom, LoopCheck::Revisit);
+        assert_eq!(cpu.run(), Halt::LoopDetected { pc: 1, acc: 5 });
+    }
+
+    #[test]
+    fn state_check_lets_loops_count_down() {
+        let (halt, out) = run("set n 3\nout n\nadd n -1\njnz n -2");
+        assert_eq!(halt, Halt::Terminated);
+        assert_eq!(out, vec![3, 2, 1]);
+        let (halt, _) = run("set n 1\njmp +0");
+        assert_eq!(halt, Halt::LoopDetected { pc: 1, acc: 0 });
+    }
+
+    #[test]
+    fn long_countdowns_are_not_loops() {
+        let rom = InstructionSet::extended()
+            .parse("set n 100000\nadd n -1\nacc +1\njnz n -2")
+            .unwrap();
+        let mut cpu = Cpu::new(&rom, LoopCheck::State);
+        assert_eq!(cpu.run(), Halt::Terminated);
+        assert_eq!(cpu.register(ACC), 100000);
+        assert_eq!(rom.registers().names(), ["acc", "n"]);
+    }
+}
@@ -0,0 +1,20 @@
+pub mod analysis;
+pub mod asm;
+pub mod debugger;
+pub mod error;
+pub mod instruction;
+pub mod isa;
+pub mod machine;
+pub mod program;
+pub mod repair;
+pub mod trace;
+
+pub use analysis::{Analysis, Verdict};
+pub use debugger::{Debugger, Stop};
+pub use error::{Error, ParseErrorKind};
+pub use instruction::Instruction;
+pub use isa::{Cpu, Flow, InstructionSet, LoopCheck, Operation, Registers, Rom, State};
+pub use machine::{Halt, Machine};
+pub use program::Program;
+pub use repair::{brute_force_repairs, repairs, Repair};
+pub use trace::{Step, Trace};
@@ -0,0 +1,64 @@
+use crate::instruction::Instruction;
+use crate::isa::{Cpu, LoopCheck, ACC};
+use crate::program::Program;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Halt {
+    // pc landed right after the last instruction
+    Terminated,
+    // the instruction at `pc` was about to run a second time
+    LoopDetected { pc: usize, acc: i64 },
+    // a jump left the program anywhere other than one past the end
+    OutOfBounds { pc: i64 },
+    // the instruction at `pc` computed a value or a jump target past i64
+    Overflow { pc: usize },
+    // LoopCheck::State had seen MAX_STATES states by the time pc was reached
+    StateLimit { pc: usize },
+}
+
+// The puzzle machine: a program's instructions on the cpu, stopping before
+// any of them runs a second time.
+#[derive(Debug, Clone)]
+pub struct Machine<'a> {
+    program: &'a Program,
+    cpu: Cpu<'a, Instruction>,
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(program: &'a Program) -> Machine<'a> {
+        Machine {
+            program,
+            cpu: Cpu::load(program.instructions(), ACC + 1, LoopCheck::Revisit),
+        }
+    }
+
+    pub fn program(&self) -> &'a Program {
+        self.program
+    }
+
+    pub fn pc(&self) -> i64 {
+        self.cpu.pc()
+    }
+
+    pub fn acc(&self) -> i64 {
+        self.cpu.register(ACC)
+    }
+
+    pub fn visited(&self, pc: usize) -> bool {
+        self.cpu.visited(pc)
+    }
+
+    // why the machine would stop instead of running the instruction at pc
+    pub fn halted(&self) -> Option<Halt> {
+        self.cpu.halted()
+    }
+
+    // runs a single instruction, or returns the halt reason without touching the state
+    pub fn step(&mut self) -> Option<Halt> {
+        self.cpu.step()
+    }
+
+    pub fn run(&mut self) -> Halt {
+        self.cpu.run()
+    }
+}
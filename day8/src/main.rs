@@ -84,89 +84,178 @@ After the last instruction (acc +6), the program terminates by attempting to run
 Fix the program so that it terminates normally by changing exactly one jmp (to nop) or nop (to jmp). What is the value of the accumulator after the program terminates?
 
 */
+use std::env;
+use std::fs;
+use std::fs::File;
 use std::io;
 use std::io::prelude::*;
-use regex::Regex;
-
-fn decode(op_in: &str, arg_in: &str) -> (u8, i32) {
-    let op: u8;
-    let arg: i32;
-    match op_in {
-        "nop" => op = 0,
-        "jmp" => op = 1,
-        "acc" => op = 2,
-        _ => op = 3
+use std::process;
+
+use day8::{
+    asm, repairs, Analysis, Cpu, Debugger, InstructionSet, LoopCheck, Machine, Program, Trace,
+};
+
+// binary roms are recognised by their magic number, anything else is text
+fn parse_or_exit(source: &[u8]) -> Program {
+    let rom = if asm::is_binary(source) {
+        asm::decode(source)
+    } else {
+        String::from_utf8_lossy(source).parse()
+    };
+    match rom {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
     }
-    arg = arg_in.parse::<i32>().unwrap();
-    return (op, arg);
 }
 
-fn run(rom: &Vec<(u8, i32)>) -> (bool, i32) {
-    let mut acc = 0;
-    let mut pc: usize = 0;
-    let mut checker: Vec<bool> = Vec::with_capacity(rom.len());
-    for _ in 0..rom.len() {
-        checker.push(false);
+// day8 asm <source> [out]: writes a binary rom to out, or canonical text to stdout
+fn assemble(args: &[String]) -> io::Result<()> {
+    if args.is_empty() || args.len() > 2 {
+        eprintln!("usage: day8 asm <source> [out]");
+        process::exit(2);
     }
-    let mut finished = false;
-    loop {
-        let (op, arg): (u8, i32) = rom[pc];
-        //println!("{} {} {} {}", op, arg, pc, acc);
-        match op {
-            0u8 => pc += 1,
-            1u8 => pc = (pc as i32 + arg) as usize,
-            2u8 => {
-                acc += arg;
-                pc += 1;
-            },
-            _ => panic!("shouldn't happen")
+    let rom = match asm::assemble(&fs::read_to_string(&args[0])?) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
         }
-        //println!("\t{} {}", pc, acc);
-        if pc == rom.len() {
-            finished = true;
-            break;
+    };
+    match args.get(1) {
+        Some(path) => fs::write(path, asm::encode(&rom)),
+        None => write!(io::stdout(), "{}", rom),
+    }
+}
+
+// day8 disasm <rom>
+fn disassemble(args: &[String]) -> io::Result<()> {
+    if args.len() != 1 {
+        eprintln!("usage: day8 disasm <rom>");
+        process::exit(2);
+    }
+    match asm::disassemble(&fs::read(&args[0])?) {
+        Ok(text) => write!(io::stdout(), "{}", text),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
         }
-        if checker[pc] {
-            break;
-        } else {
-            checker[pc] = true;
+    }
+}
+
+// day8 debug <rom> [commands]: with no command file the session reads stdin
+fn debug(args: &[String]) -> io::Result<()> {
+    if args.is_empty() || args.len() > 2 {
+        eprintln!("usage: day8 debug <rom> [commands]");
+        process::exit(2);
+    }
+    let rom = parse_or_exit(&fs::read(&args[0])?);
+    let mut debugger = Debugger::new(&rom);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match args.get(1) {
+        Some(path) => debugger.run_script(io::BufReader::new(File::open(path)?), &mut out, false),
+        None => {
+            let stdin = io::stdin();
+            let input = stdin.lock();
+            debugger.run_script(input, &mut out, true)
         }
     }
-    return (finished, acc);
 }
 
-fn main() {
-    let stdio = io::stdin();
-    let re = Regex::new(r"^(?P<op>\w+) (?P<arg>[+-]\d+)").unwrap();
-    let mut rom = Vec::new();
-    for line in stdio.lock().lines() {
-        let sline = line.unwrap().to_owned();
-        let cap = re.captures(&sline).unwrap();
-        let op = &cap["op"];
-        let arg = &cap["arg"];
-        rom.push(decode(op, arg));
+// day8 trace <rom> [jsonl|dot]: writes the execution trace or the control-flow graph
+fn trace(args: &[String]) -> io::Result<()> {
+    let format = args.get(1).map(|a| a.as_str()).unwrap_or("jsonl");
+    if args.is_empty() || args.len() > 2 || (format != "jsonl" && format != "dot") {
+        eprintln!("usage: day8 trace <rom> [jsonl|dot]");
+        process::exit(2);
     }
-    //println!("{:?}", rom);
-    let (_, acc) = run(&rom);
-    println!("{}", acc);
-
-    // O(n^2) can be better (maybe O(n)?)
-    for i in 1..rom.len() {
-        let old = rom[i];
-        let (op, arg) = old;
-        if op == 0 {
-            rom[i] = (1, arg)
-        } else if op == 1 {
-            rom[i] = (0, arg);
-        } else {
-            continue;
+    let rom = parse_or_exit(&fs::read(&args[0])?);
+    let trace = Trace::record(&mut Machine::new(&rom));
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if format == "dot" {
+        trace.write_dot(&rom, &mut out)
+    } else {
+        trace.write_jsonl(&mut out)
+    }
+}
+
+// day8 run <rom> [revisit|state]: runs a rom through the extended instruction set
+fn run(args: &[String]) -> io::Result<()> {
+    let check = match args.get(1).map(|a| a.as_str()) {
+        None | Some("revisit") => LoopCheck::Revisit,
+        Some("state") => LoopCheck::State,
+        Some(_) => {
+            eprintln!("usage: day8 run <rom> [revisit|state]");
+            process::exit(2);
         }
-        let (ok, acc) = run(&rom);
-        if ok {
-            println!("{}", acc);
-            break;
-        } else {
-            rom[i] = old;
+    };
+    if args.is_empty() || args.len() > 2 {
+        eprintln!("usage: day8 run <rom> [revisit|state]");
+        process::exit(2);
+    }
+    let rom = match InstructionSet::extended().parse(&fs::read_to_string(&args[0])?) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let mut cpu = Cpu::new(&rom, check);
+    let halt = cpu.run();
+    println!("{:?}", halt);
+    let names = rom.registers().names();
+    for (name, value) in names.iter().zip(cpu.registers()) {
+        println!("{} = {}", name, value);
+    }
+    for value in cpu.output() {
+        println!("out {}", value);
+    }
+    Ok(())
+}
+
+// day8 check <rom>: static report of blocks, loops and per-instruction verdicts
+fn check(args: &[String]) -> io::Result<()> {
+    if args.len() != 1 {
+        eprintln!("usage: day8 check <rom>");
+        process::exit(2);
+    }
+    let rom = parse_or_exit(&fs::read(&args[0])?);
+    write!(io::stdout(), "{}", Analysis::analyze(&rom))
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match args.first().map(|a| a.as_str()) {
+        Some("debug") => Some(debug as fn(&[String]) -> io::Result<()>),
+        Some("trace") => Some(trace as fn(&[String]) -> io::Result<()>),
+        Some("run") => Some(run as fn(&[String]) -> io::Result<()>),
+        Some("asm") => Some(assemble as fn(&[String]) -> io::Result<()>),
+        Some("disasm") => Some(disassemble as fn(&[String]) -> io::Result<()>),
+        Some("check") => Some(check as fn(&[String]) -> io::Result<()>),
+        _ => None,
+    };
+    if let Some(command) = command {
+        if let Err(e) = command(&args[1..]) {
+            eprintln!("{}", e);
+            process::exit(1);
         }
+        return;
+    }
+
+    let stdin = io::stdin();
+    let mut buffer = Vec::new();
+    stdin.lock().read_to_end(&mut buffer).unwrap();
+    let rom = parse_or_exit(&buffer);
+
+    let mut machine = Machine::new(&rom);
+    machine.run();
+    println!("{}", machine.acc());
+
+    if let Some(repair) = repairs(&rom).first() {
+        println!("{}", repair.acc);
     }
 }
@@ -0,0 +1,83 @@
+use std::fmt;
+use std::ops::Index;
+use std::str::FromStr;
+
+use crate::error::Error;
+use crate::instruction::Instruction;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+}
+
+impl Program {
+    pub fn new(instructions: Vec<Instruction>) -> Program {
+        Program { instructions }
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    pub fn get(&self, pc: usize) -> Option<&Instruction> {
+        self.instructions.get(pc)
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    // copy of the program with the jmp/nop at `pc` swapped, None if it is an acc
+    pub fn with_flipped(&self, pc: usize) -> Option<Program> {
+        let flipped = self.instructions.get(pc)?.flipped()?;
+        let mut instructions = self.instructions.clone();
+        instructions[pc] = flipped;
+        Some(Program { instructions })
+    }
+}
+
+impl Index<usize> for Program {
+    type Output = Instruction;
+
+    fn index(&self, pc: usize) -> &Instruction {
+        &self.instructions[pc]
+    }
+}
+
+impl From<Vec<Instruction>> for Program {
+    fn from(instructions: Vec<Instruction>) -> Program {
+        Program { instructions }
+    }
+}
+
+impl FromStr for Program {
+    type Err = Error;
+
+    // blank lines are skipped, errors report the 1-based source line
+    fn from_str(s: &str) -> Result<Program, Error> {
+        let mut instructions = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let instruction = line
+                .parse::<Instruction>()
+                .map_err(|kind| Error::Parse { line: i + 1, kind })?;
+            instructions.push(instruction);
+        }
+        Ok(Program { instructions })
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for instruction in self.instructions.iter() {
+            writeln!(f, "{}", instruction)?;
+        }
+        Ok(())
+    }
+}
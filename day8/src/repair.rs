@@ -0,0 +1,181 @@
+use std::collections::VecDeque;
+
+use crate::instruction::Instruction;
+use crate::machine::{Halt, Machine};
+use crate::program::Program;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Repair {
+    pub pc: usize,
+    pub from: Instruction,
+    pub to: Instruction,
+    // accumulator once the repaired program terminates
+    pub acc: i64,
+}
+
+// where control goes after running `instruction` at `pc`, None if it leaves the program
+fn successor(instruction: Instruction, pc: usize, len: usize) -> Option<usize> {
+    let next = instruction.next_pc(pc as i64);
+    if next >= 0 && next <= len as i64 {
+        Some(next as usize)
+    } else {
+        None
+    }
+}
+
+fn acc_delta(instruction: Instruction) -> i64 {
+    match instruction {
+        Instruction::Acc(arg) => i64::from(arg),
+        _ => 0,
+    }
+}
+
+// Finds every single jmp/nop flip that makes a non-terminating program terminate.
+//
+// The reverse control-flow graph is walked once from the exit (pc == len) to find
+// which instructions already lead to termination and what they add to the
+// accumulator on the way. The original run is then replayed once: a flip at a
+// visited pc fixes the program iff its new successor is one of those instructions.
+// Repairs come back sorted by pc; a program that already terminates needs no
+// repair and gets an empty list.
+pub fn repairs(program: &Program) -> Vec<Repair> {
+    let len = program.len();
+    let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); len + 1];
+    for (pc, instruction) in program.instructions().iter().enumerate() {
+        if let Some(next) = successor(*instruction, pc, len) {
+            predecessors[next].push(pc);
+        }
+    }
+
+    // acc_to_end[pc] is Some(acc added from pc to the exit) if pc reaches the exit
+    let mut acc_to_end: Vec<Option<i64>> = vec![None; len + 1];
+    acc_to_end[len] = Some(0);
+    let mut queue = VecDeque::new();
+    queue.push_back(len);
+    while let Some(node) = queue.pop_front() {
+        let rest = acc_to_end[node].unwrap();
+        for &pc in predecessors[node].iter() {
+            if acc_to_end[pc].is_none() {
+                acc_to_end[pc] = Some(rest + acc_delta(program[pc]));
+                queue.push_back(pc);
+            }
+        }
+    }
+
+    if acc_to_end.first().copied().flatten().is_some() {
+        return Vec::new();
+    }
+
+    // the original run never reaches the exit, so none of the pcs it visits do
+    // either and a flipped instruction can never be reached again after the flip
+    let mut found = Vec::new();
+    let mut visited = vec![false; len];
+    let mut pc = 0usize;
+    let mut acc = 0i64;
+    while pc < len && !visited[pc] {
+        visited[pc] = true;
+        let instruction = program[pc];
+        if let Some(to) = instruction.flipped() {
+            if let Some(rest) = successor(to, pc, len).and_then(|next| acc_to_end[next]) {
+                found.push(Repair {
+                    pc,
+                    from: instruction,
+                    to,
+                    acc: acc + rest,
+                });
+            }
+        }
+        acc += acc_delta(instruction);
+        pc = match successor(instruction, pc, len) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    found.sort_by_key(|repair| repair.pc);
+    found
+}
+
+// Reference implementation: flip every jmp/nop and re-run the whole program.
+pub fn brute_force_repairs(program: &Program) -> Vec<Repair> {
+    let mut original = Machine::new(program);
+    if original.run() == Halt::Terminated {
+        return Vec::new();
+    }
+    let mut found = Vec::new();
+    for pc in 0..program.len() {
+        let patched = match program.with_flipped(pc) {
+            Some(patched) => patched,
+            None => continue,
+        };
+        let mut machine = Machine::new(&patched);
+        if machine.run() == Halt::Terminated {
+            found.push(Repair {
+                pc,
+                from: program[pc],
+                to: patched[pc],
+                acc: machine.acc(),
+            });
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    const EXAMPLE: &str = "nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6";
+
+    #[test]
+    fn puzzle_example() {
+        let program: Program = EXAMPLE.parse().unwrap();
+        let expected = vec![Repair {
+            pc: 7,
+            from: Instruction::Jmp(-4),
+            to: Instruction::Nop(-4),
+            acc: 8,
+        }];
+        assert_eq!(repairs(&program), expected);
+        assert_eq!(brute_force_repairs(&program), expected);
+    }
+
+    #[test]
+    fn no_valid_repair() {
+        // either flip leaves the other jmp +0 spinning in place
+        let program: Program = "jmp +0\njmp +0\n".parse().unwrap();
+        assert_eq!(repairs(&program), vec![]);
+        assert_eq!(brute_force_repairs(&program), vec![]);
+        // one that already terminates needs none
+        let program: Program = "nop +0\nacc +1\n".parse().unwrap();
+        assert_eq!(repairs(&program), vec![]);
+        assert_eq!(brute_force_repairs(&program), vec![]);
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(8);
+        for _ in 0..500 {
+            let len = rng.random_range(1..20);
+            let instructions: Vec<Instruction> = (0..len)
+                .map(|_| {
+                    let arg = rng.random_range(-5..=5);
+                    match rng.random_range(0..3) {
+                        0 => Instruction::Nop(arg),
+                        1 => Instruction::Jmp(arg),
+                        _ => Instruction::Acc(arg),
+                    }
+                })
+                .collect();
+            let program = Program::new(instructions);
+            assert_eq!(
+                repairs(&program),
+                brute_force_repairs(&program),
+                "{}",
+                program
+            );
+        }
+    }
+}
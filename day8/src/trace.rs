@@ -0,0 +1,221 @@
+use std::collections::HashSet;
+use std::io;
+use std::io::prelude::*;
+
+use crate::instruction::Instruction;
+use crate::machine::{Halt, Machine};
+use crate::program::Program;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    pub pc: usize,
+    pub instruction: Instruction,
+    pub acc_before: i64,
+    pub acc_after: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    steps: Vec<Step>,
+    halt: Option<Halt>,
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        Trace::default()
+    }
+
+    // runs `machine` to a halt, recording each instruction it executes
+    pub fn record(machine: &mut Machine) -> Trace {
+        let mut trace = Trace::new();
+        loop {
+            let pc = machine.pc();
+            let acc_before = machine.acc();
+            if let Some(halt) = machine.step() {
+                trace.halt = Some(halt);
+                return trace;
+            }
+            let pc = pc as usize;
+            trace.steps.push(Step {
+                pc,
+                instruction: machine.program()[pc],
+                acc_before,
+                acc_after: machine.acc(),
+            });
+        }
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn halt(&self) -> Option<Halt> {
+        self.halt
+    }
+
+    // pcs of the loop the run got stuck in, in execution order
+    pub fn cycle(&self) -> Vec<usize> {
+        match self.halt {
+            Some(Halt::LoopDetected { pc, .. }) => {
+                let start = self
+                    .steps
+                    .iter()
+                    .position(|step| step.pc == pc)
+                    .unwrap_or(0);
+                self.steps[start..].iter().map(|step| step.pc).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // one JSON object per executed instruction
+    pub fn write_jsonl<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for (i, step) in self.steps.iter().enumerate() {
+            writeln!(
+                out,
+                "{{\"step\":{},\"pc\":{},\"op\":\"{}\",\"arg\":{},\"acc_before\":{},\"acc_after\":{}}}",
+                i,
+                step.pc,
+                step.instruction.mnemonic(),
+                step.instruction.arg(),
+                step.acc_before,
+                step.acc_after
+            )?;
+        }
+        Ok(())
+    }
+
+    // Control-flow graph of `program`: executed instructions are shaded and the
+    // loop that stopped the run, if any, is drawn in red.
+    pub fn write_dot<W: Write>(&self, program: &Program, out: &mut W) -> io::Result<()> {
+        let len = program.len() as i64;
+        let visited: HashSet<usize> = self.steps.iter().map(|step| step.pc).collect();
+        let cycle: HashSet<usize> = self.cycle().into_iter().collect();
+
+        writeln!(out, "digraph rom {{")?;
+        writeln!(out, "    node [shape=box, fontname=monospace];")?;
+        for (pc, instruction) in program.instructions().iter().enumerate() {
+            let style = if cycle.contains(&pc) {
+                ", style=filled, fillcolor=\"#ffb3b3\", color=red"
+            } else if visited.contains(&pc) {
+                ", style=filled, fillcolor=lightgrey"
+            } else {
+                ""
+            };
+            writeln!(
+                out,
+                "    n{} [label=\"{}: {}\"{}];",
+                pc, pc, instruction, style
+            )?;
+        }
+        writeln!(out, "    end [shape=doublecircle, label=\"end\"];")?;
+
+        let mut out_of_bounds = HashSet::new();
+        for (pc, instruction) in program.instructions().iter().enumerate() {
+            let next = instruction.next_pc(pc as i64);
+            let target = if next == len {
+                "end".to_owned()
+            } else if next < 0 || next > len {
+                let id = format!("oob{}", next.to_string().replace('-', "m"));
+                if out_of_bounds.insert(next) {
+                    writeln!(
+                        out,
+                        "    {} [shape=octagon, style=dashed, label=\"out of bounds: {}\"];",
+                        id, next
+                    )?;
+                }
+                id
+            } else {
+                format!("n{}", next)
+            };
+            let style = if cycle.contains(&pc) {
+                " [color=red, penwidth=2]"
+            } else {
+                ""
+            };
+            writeln!(out, "    n{} -> {}{};", pc, target, style)?;
+        }
+        writeln!(out, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(source: &str) -> (Program, Trace) {
+        let program: Program = source.parse().unwrap();
+        let trace = Trace::record(&mut Machine::new(&program));
+        (program, trace)
+    }
+
+    fn jsonl(trace: &Trace) -> String {
+        let mut out = Vec::new();
+        trace.write_jsonl(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn dot(program: &Program, trace: &Trace) -> String {
+        let mut out = Vec::new();
+        trace.write_dot(program, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn stuck_in_a_loop() {
+        let (program, trace) = record("nop +0\nacc +2\njmp -1\njmp -9\n");
+        assert_eq!(trace.halt(), Some(Halt::LoopDetected { pc: 1, acc: 2 }));
+        assert_eq!(trace.cycle(), vec![1, 2]);
+        assert_eq!(
+            jsonl(&trace),
+            r#"{"step":0,"pc":0,"op":"nop","arg":0,"acc_before":0,"acc_after":0}
+{"step":1,"pc":1,"op":"acc","arg":2,"acc_before":0,"acc_after":2}
+{"step":2,"pc":2,"op":"jmp","arg":-1,"acc_before":2,"acc_after":2}
+"#
+        );
+        assert_eq!(
+            dot(&program, &trace),
+            r##"digraph rom {
+    node [shape=box, fontname=monospace];
+    n0 [label="0: nop +0", style=filled, fillcolor=lightgrey];
+    n1 [label="1: acc +2", style=filled, fillcolor="#ffb3b3", color=red];
+    n2 [label="2: jmp -1", style=filled, fillcolor="#ffb3b3", color=red];
+    n3 [label="3: jmp -9"];
+    end [shape=doublecircle, label="end"];
+    n0 -> n1;
+    n1 -> n2 [color=red, penwidth=2];
+    n2 -> n1 [color=red, penwidth=2];
+    oobm6 [shape=octagon, style=dashed, label="out of bounds: -6"];
+    n3 -> oobm6;
+}
+"##
+        );
+    }
+
+    #[test]
+    fn terminates() {
+        let (program, trace) = record("acc -1\njmp +2\nacc +5\n");
+        assert_eq!(trace.halt(), Some(Halt::Terminated));
+        assert_eq!(trace.cycle(), vec![]);
+        assert_eq!(
+            jsonl(&trace),
+            r#"{"step":0,"pc":0,"op":"acc","arg":-1,"acc_before":0,"acc_after":-1}
+{"step":1,"pc":1,"op":"jmp","arg":2,"acc_before":-1,"acc_after":-1}
+"#
+        );
+        assert_eq!(
+            dot(&program, &trace),
+            r#"digraph rom {
+    node [shape=box, fontname=monospace];
+    n0 [label="0: acc -1", style=filled, fillcolor=lightgrey];
+    n1 [label="1: jmp +2", style=filled, fillcolor=lightgrey];
+    n2 [label="2: acc +5"];
+    end [shape=doublecircle, label="end"];
+    n0 -> n1;
+    n1 -> end;
+    n2 -> end;
+}
+"#
+        );
+    }
+}